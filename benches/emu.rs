@@ -1,17 +1,14 @@
 use criterion::{criterion_group, criterion_main, Criterion};
 use rust_emu::emu::Emu;
-use rust_emu::instructions::INSTR_TABLE;
+use rust_emu::gpu::FRAME_CYCLES;
 
 fn criterion_benchmark(c: &mut Criterion) {
-    c.bench_function("Emu step", |b| {
+    // Mostly ALU ops on registers, so this is dominated by operand decoding.
+    let rom = include_bytes!("../test_roms/09-op r,r.gb").to_vec();
+    c.bench_function("Emu 10 frames", |b| {
         b.iter(|| {
-            let mut emu = Emu::new(vec![]);
-            let mut bus = emu.bus;
-            bus.in_bios = 1;
-            for _instr in INSTR_TABLE.iter() {
-                // emu.cpu.opcode = instr;
-                emu.cpu.step(&mut bus);
-            }
+            let mut emu = Emu::new(rom.clone(), None);
+            emu.run_cycles(FRAME_CYCLES * 10);
         })
     });
 }
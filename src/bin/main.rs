@@ -3,11 +3,9 @@ extern crate imgui_opengl_renderer;
 //SDL
 
 use crate::constants::GB_CYCLE_SPEED;
-use crate::constants::CYCLES_PER_FRAME;
 use crate::constants::FRAME_TIME;
 use crate::constants::WINDOW_HEIGHT;
 use crate::constants::WINDOW_WIDTH;
-use crate::constants::MAP_WIDTH;
 
 use crate::debugger::Imgui;
 use imgui::Slider;
@@ -24,9 +22,11 @@ use std::time::Instant;
 use std::path::PathBuf;
 
 //File IO
+use std::fs::File;
+use std::io::BufWriter;
 use log::info;
 
-use gpu::{PixelData};
+use gpu::{PixelData, ScreenData};
 use rust_emu::{cpu::JOYPAD, emu::gen_il, emu::Emu, debugger};
 use structopt::StructOpt;
 
@@ -44,6 +44,15 @@ struct Settings {
     bootrom: Option<PathBuf>,
     #[structopt(short = "-r")]
     repl: bool,
+    // IPS, UPS or BPS patches, applied in order.
+    #[structopt(short = "-p", long = "patch", parse(from_os_str))]
+    patches: Vec<PathBuf>,
+    // Writes a Gameboy Doctor log of every instruction run.
+    #[structopt(long = "trace", parse(from_os_str))]
+    trace: Option<PathBuf>,
+    // Hardware to emulate: dmg0, dmg, mgb, sgb, sgb2, cgb or agb.
+    #[structopt(short = "-m", long = "model", default_value = "dmg")]
+    model: model::Model,
 }
 
 
@@ -75,7 +84,15 @@ fn main() -> MaybeErr<()> {
         setup_logger()?;
     }
     info!("Running SDL Main");
-    let mut emu = Emu::from_path(settings.input, settings.bootrom)?;
+    let mut emu = Emu::from_path(settings.input, settings.bootrom, &settings.patches)?;
+    emu.bus.model = settings.model;
+    if let Some(path) = settings.trace {
+        emu.trace_to(BufWriter::new(File::create(path)?));
+    }
+    emu.add_listener(|event| match event {
+        emu::Event::Rumble(on) => info!("Rumble motor {}", if *on { "on" } else { "off" }),
+        emu::Event::Locked(addr) => info!("CPU locked at ${:04X}", addr),
+    });
     let context = sdl2::init()?;
 
     let video = context.video()?;
@@ -98,6 +115,8 @@ fn main() -> MaybeErr<()> {
     let mut debugger = Imgui::new(&debugger)?;
 
     sdl_main(&mut rsboy, &mut debugger, &context, &mut emu)?;
+    emu.save()?;
+    emu.save_cheats()?;
     map_viewer(&context, &emu)?;
     vram_viewer(&context, &emu)
 }
@@ -119,7 +138,7 @@ fn sdl_main(video: &mut sdl2::render::Canvas<Window>, debugger: &mut Imgui, cont
 
     let mut event_pump = context.event_pump()?;
 
-    let il = gen_il(&emu.bus.memory);
+    let il = gen_il(emu.bus.cartridge.rom());
     debugger.info.il = il;
 
     loop {
@@ -177,17 +196,13 @@ fn sdl_main(video: &mut sdl2::render::Canvas<Window>, debugger: &mut Imgui, cont
         let mut delta_clock = 0;
         if !pause {
             let before = emu.bus.clock;
-            while emu.bus.clock < before + CYCLES_PER_FRAME {
-                emu.emulate_step();
-            }
+            emu.run_frame();
             delta_clock = emu.bus.clock - before;
         }
-        // Render to framebuffer and copy.
+        // Copy the finished screen.
         {
             let time = now.elapsed();
-            emu.bus.gpu.render(&mut emu.framebuffer);
-            let (h, v) = emu.bus.gpu.scroll();
-            texture.copy_window(h, v, &emu.framebuffer);
+            texture.copy_screen(&emu.bus.gpu.screen);
             video.copy(&texture, None, None).unwrap();
             video.present();
             delay_min(time);
@@ -215,10 +230,7 @@ fn sdl_main(video: &mut sdl2::render::Canvas<Window>, debugger: &mut Imgui, cont
                 .range(0..=(69905))
                 .build(ui, &mut cycle_jump);
             if ui.button(im_str!("Go"), [200.0, 50.0]) {
-                let before = emu.bus.clock as i32;
-                while emu.bus.clock < (before + cycle_jump) as usize {
-                    emu.emulate_step();
-                }
+                emu.run_cycles(cycle_jump.max(0) as usize);
             }
             ui.text(format!("Bus Info:\n{}", emu.bus));
             ui.text(format!("GPU Info:\n{}", emu.bus.gpu));
@@ -227,11 +239,10 @@ fn sdl_main(video: &mut sdl2::render::Canvas<Window>, debugger: &mut Imgui, cont
             }
             if ui.button(im_str!("Frame"), [200.0, 50.0]) {
                 println!("Frame");
-                let before = emu.bus.clock;
-                while emu.bus.clock < before + CYCLES_PER_FRAME {
-                    emu.emulate_step();
-                }
+                emu.run_frame();
             }
+            debugger::disassembly_ui(info, ui, emu.cpu.op_addr);
+            debugger::cheats_ui(info, ui, &mut emu.bus.cheats);
         });
     }
 }
@@ -245,21 +256,14 @@ fn delay_min(elapsed: Duration) {
 
 
 trait GBWindow {
-    fn copy_window(&mut self, h: u32, v: u32, buffer: &PixelData);
+    fn copy_screen(&mut self, screen: &ScreenData);
     fn copy_map(&mut self, buffer: &PixelData);
 }
 impl GBWindow for Texture<'_> {
-    fn copy_window(&mut self, horz: u32, vert: u32, framebuffer: &PixelData) {
+    fn copy_screen(&mut self, screen: &ScreenData) {
         self.with_lock(None, |buffer, _| {
-            let mut i = 0;
-            for y in vert..vert + WINDOW_HEIGHT {
-                let y = (y % MAP_WIDTH) as usize;
-                for x in horz..horz + WINDOW_WIDTH {
-                    let x = (x % MAP_WIDTH) as usize;
-                    let bytes = framebuffer[y][x].to_be_bytes();
-                    buffer[i..(i + 4)].copy_from_slice(&bytes);
-                    i += 4;
-                }
+            for (bytes, pixel) in buffer.chunks_exact_mut(4).zip(screen.iter().flatten()) {
+                bytes.copy_from_slice(&pixel.to_be_bytes());
             }
         })
         .unwrap();
@@ -295,7 +299,9 @@ fn map_viewer(sdl_context: &sdl2::Sdl, emu: &emu::Emu) -> Result<(), String> {
         .map_err(|e| e.to_string())?;
 
     // Pitch = n_bytes(3) * map_w * tile_w
-    texture.copy_map(&emu.framebuffer);
+    let mut map = Box::new([[0; 256]; 256]);
+    gpu.render_map(&mut map);
+    texture.copy_map(&map);
     canvas.copy(&texture, None, None)?;
     let (h, v) = gpu.scroll();
     println!("{} {}", h, v);
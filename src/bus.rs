@@ -1,11 +1,19 @@
+use crate::cartridge;
+use crate::cartridge::Cartridge;
+use crate::cheats::Cheats;
 use crate::gpu::GPU;
+use crate::gpu::LCD_REGISTERS_END;
+use crate::gpu::LCD_REGISTERS_START;
 use crate::gpu::OAM_END;
 use crate::gpu::OAM_START;
 use crate::gpu::VRAM_END;
 use crate::gpu::VRAM_START;
+use crate::io::io_register;
+use crate::model::Model;
 use crate::timer;
 use crate::timer::Timer;
 use std::io::Read;
+use std::ops::RangeInclusive;
 use std::{fmt::Display, fs::File};
 use std::path::PathBuf;
 
@@ -14,6 +22,57 @@ pub trait Memory {
     fn write(&mut self, address: u16, value: u8);
 }
 
+// A memory mapped peripheral. Devices are ticked every bus cycle and raise
+// interrupts by setting bits in `int_flags`.
+pub trait Device {
+    fn read(&self, address: u16) -> u8;
+    fn write(&mut self, address: u16, value: u8, int_flags: &mut u8);
+    fn tick(&mut self, _int_flags: &mut u8) {}
+}
+
+pub const WRAM_START: usize = 0xC000;
+pub const WRAM_END: usize = 0xDFFF;
+pub const ECHO_START: usize = 0xE000;
+pub const ECHO_END: usize = 0xFDFF;
+pub const UNUSABLE_START: usize = 0xFEA0;
+pub const UNUSABLE_END: usize = 0xFEFF;
+pub const IO_START: usize = 0xFF00;
+pub const IO_END: usize = 0xFF7F;
+pub const HRAM_START: usize = 0xFF80;
+pub const HRAM_END: usize = 0xFFFE;
+
+// Plain RAM starting at `start`. Addresses past the end wrap around,
+// which is how echo RAM mirrors WRAM.
+pub struct Ram {
+    start: u16,
+    data: Vec<u8>,
+}
+
+impl Ram {
+    pub fn new(start: usize, size: usize) -> Self {
+        Self {
+            start: start as u16,
+            data: vec![0; size],
+        }
+    }
+
+    fn index(&self, address: u16) -> usize {
+        address.wrapping_sub(self.start) as usize % self.data.len()
+    }
+}
+
+impl Device for Ram {
+    fn read(&self, address: u16) -> u8 {
+        self.data[self.index(address)]
+    }
+    fn write(&mut self, address: u16, value: u8, _int_flags: &mut u8) {
+        let i = self.index(address);
+        self.data[i] = value;
+    }
+}
+
+type Mapping = (RangeInclusive<u16>, Box<dyn Device>);
+
 pub enum Select {
     Buttons,
     Directions,
@@ -22,7 +81,11 @@ pub enum Select {
 
 // Global emu struct.
 pub struct Bus {
-    pub memory: [u8; 0x10000],
+    pub wram: Ram,
+    pub hram: Ram,
+    pub io_registers: [u8; 0x80],
+    pub cartridge: Box<dyn Cartridge>,
+    devices: Vec<Mapping>,
     pub bootrom: [u8; 0x100],
     pub in_bios: u8,
     pub int_enabled: u8,
@@ -36,6 +99,14 @@ pub struct Bus {
     pub rom_start_signal: bool,
     pub timer: Timer,
     pub io: String,
+    pub model: Model,
+    pub cheats: Cheats,
+    // CGB speed switch, KEY1 bit 7 and bit 0.
+    pub double_speed: bool,
+    pub speed_switch_armed: bool,
+    // Gameboy Doctor's reference logs are taken with LY stuck at 0x90, so
+    // tracing needs it to read that way too.
+    pub doctor: bool,
 }
 
 impl Display for Bus {
@@ -58,12 +129,15 @@ impl Display for Bus {
 
 impl Bus {
     pub fn new(rom_vec: Vec<u8>, bootrom_path: Option<PathBuf>) -> Self {
-        let mut memory = [0; 0x10000];
         let mut buffer = Vec::new();
-        let mut bootrom = [0; 0x100];
+        let bootrom = [0; 0x100];
 
         let mut bus = Bus {
-            memory,
+            wram: Ram::new(WRAM_START, 0x2000),
+            hram: Ram::new(HRAM_START, 0x7F),
+            io_registers: [0; 0x80],
+            cartridge: cartridge::from_rom(rom_vec),
+            devices: vec![],
             bootrom,
             in_bios: 0,
             int_enabled: 0,
@@ -71,12 +145,17 @@ impl Bus {
             clock: 0,
             ime: 0,
             select: Select::Buttons,
-            directions: 0,
-            keypresses: 0,
+            directions: 0x0F,
+            keypresses: 0x0F,
             gpu: GPU::new(),
             rom_start_signal: false,
             timer: Timer::new(),
             io: String::new(),
+            model: Model::default(),
+            cheats: Cheats::default(),
+            double_speed: false,
+            speed_switch_armed: false,
+            doctor: false,
         };
 
         if let Ok(mut file) = File::open(bootrom_path.unwrap_or("dmg_boot.bin".into())) {
@@ -86,13 +165,129 @@ impl Bus {
         } else {
             bus.in_bios = 1;
             bus.rom_start_signal = true;
-            println!("No bootrom provided.");
+            println!("No bootrom provided, starting from the post-boot state.");
         }
-        bus.memory[..rom_vec.len()].clone_from_slice(&rom_vec[..]);
 
         bus
     }
 
+    // Maps `device` over `range`, in front of everything else on the bus.
+    // Useful for plugging in test devices or new hardware at arbitrary addresses.
+    pub fn map_device(&mut self, range: RangeInclusive<u16>, device: Box<dyn Device>) {
+        self.devices.push((range, device));
+    }
+
+    fn device(&self, address: u16) -> Option<&dyn Device> {
+        self.devices
+            .iter()
+            .find(|(range, _)| range.contains(&address))
+            .map(|(_, device)| device.as_ref())
+    }
+
+    // What the DMG boot ROM leaves in VRAM: the cartridge's logo blown up to
+    // 24 tiles from 0x8010, the (R) tile after it, and the map showing both.
+    pub fn load_boot_logo(&mut self) {
+        // Each bit becomes two pixels wide and each row two rows tall.
+        let double = |nibble: u8| {
+            (0..4).fold(0, |row, bit| {
+                row << 2 | if nibble & (0b1000 >> bit) != 0 { 0b11 } else { 0 }
+            })
+        };
+        let mut address = 0x8010;
+        for i in 0x0104..0x0134 {
+            let byte = self.read(i);
+            for nibble in [byte >> 4, byte & 0x0F] {
+                for _ in 0..2 {
+                    self.write(address, double(nibble));
+                    address += 2;
+                }
+            }
+        }
+        for (i, row) in [0x3C, 0x42, 0xB9, 0xA5, 0xB9, 0xA5, 0x42, 0x3C].iter().enumerate() {
+            self.write(0x8190 + 2 * i as u16, *row);
+        }
+        for tile in 1..=12 {
+            self.write(0x9903 + tile as u16, tile);
+            self.write(0x9923 + tile as u16, tile + 12);
+        }
+        self.write(0x9910, 0x19);
+    }
+
+    // Low nibble of P1, a 0 bit is a pressed button on the selected line.
+    pub fn joypad_lines(&self) -> u8 {
+        let buttons = match self.select {
+            Select::Buttons => self.keypresses,
+            Select::Directions => self.directions,
+            Select::None => 0xFF,
+        };
+        buttons & 0x0F
+    }
+
+    // Called by STOP when KEY1 is armed.
+    pub fn switch_speed(&mut self) {
+        self.speed_switch_armed = false;
+        self.double_speed = !self.double_speed;
+    }
+
+    // While stopped only time passes, the LCD and timer are frozen.
+    pub fn stopped_cycle(&mut self) {
+        self.clock += 1;
+        self.cartridge.tick();
+    }
+
+    // Unused bits read as 1 and unmapped registers as 0xFF, see `io::io_register`.
+    fn io_read(&self, address: u16) -> u8 {
+        let value = match address as usize {
+            0xff00 => (self.io_registers[0] & 0x30) | self.joypad_lines(),
+            0xff0f => self.int_flags,
+            0xff44 if self.doctor => 0x90,
+            timer::DIV..=timer::TAC => self.timer.read(address),
+            LCD_REGISTERS_START..=LCD_REGISTERS_END if address != 0xFF46 => self.gpu.read(address),
+            0xff4d => (self.double_speed as u8) << 7 | self.speed_switch_armed as u8,
+            _ => self.io_registers[address as usize - IO_START],
+        };
+        value | io_register(address, self.model).unused
+    }
+
+    fn io_write(&mut self, address: u16, value: u8) {
+        let writable = io_register(address, self.model).writable;
+        let value = value & writable;
+        match address as usize {
+            0xff00 => {
+                self.select = match value & 0xF0 {
+                    0b0001_0000 => Select::Buttons,
+                    0b0010_0000 => Select::Directions,
+                    0b0011_0000 => Select::None,
+                    _ => Select::None,
+                }
+            }
+            0xff02 if value == 0x81 => {
+                self.io.push(char::from(self.io_registers[0x01]));
+            }
+            0xff0f => self.int_flags = value,
+            timer::DIV..=timer::TAC => self.timer.write(address, value, &mut self.int_flags),
+            0xff46 => {
+                //OAM Transfer request
+                let value = value as u16;
+                if value <= 0xF1 {
+                    let start = value << 8;
+                    for i in 0..self.gpu.oam.len() as u16 {
+                        self.gpu.oam[i as usize] = self.read(start | i);
+                    }
+                }
+            }
+            LCD_REGISTERS_START..=LCD_REGISTERS_END => {
+                self.gpu.write(address, value, &mut self.int_flags)
+            }
+            0xff4d => self.speed_switch_armed = value & 1 != 0,
+            // The boot ROM has already set everything up, this only unmaps it.
+            0xff50 => self.in_bios = value,
+            _ => {}
+        }
+        let register = &mut self.io_registers[address as usize - IO_START];
+        *register = (*register & !writable) | value;
+    }
+
     pub fn enable_interrupts(&mut self) {
         self.ime = 1;
     }
@@ -101,15 +296,20 @@ impl Bus {
         self.ime = 0;
     }
 
-    pub fn ack_interrupt(&mut self, flag: u8) {
-        self.ime = 0;
-        self.int_flags &= !flag;
-    }
-
     pub fn generic_cycle(&mut self) {
         self.clock += 1;
-        self.gpu.cycle(&mut self.int_flags);
-        self.timer.tick_timer_counter(&mut self.int_flags);
+        let frame = self.gpu.vblank_count;
+        self.gpu.tick(&mut self.int_flags);
+        if self.gpu.vblank_count != frame && !self.cheats.list.is_empty() {
+            let cheats = std::mem::take(&mut self.cheats);
+            cheats.write_ram(self);
+            self.cheats = cheats;
+        }
+        self.timer.tick(&mut self.int_flags);
+        self.cartridge.tick();
+        for (_, device) in &mut self.devices {
+            device.tick(&mut self.int_flags);
+        }
     }
 
     pub fn read_cycle(&mut self, addr: u16) -> u8 {
@@ -130,100 +330,126 @@ impl Bus {
 
 impl Memory for Bus {
     fn read(&self, address: u16) -> u8 {
+        if let Some(device) = self.device(address) {
+            return device.read(address);
+        }
         match address as usize {
-            0x0000..=0x0100 if self.in_bios == 0 => self.bootrom[address as usize],
-            timer::DIV => self.timer.div(),
-            timer::TAC => self.timer.tac,
-            timer::TMA => self.timer.tma,
-            timer::TIMA => self.timer.tima,
-            0xFF40 => self.gpu.lcdc,
-            0xFF41 => self.gpu.lcdstat,
-            0xFF42 => self.gpu.scrolly,
-            0xFF43 => self.gpu.scrollx,
-            0xFF44 => self.gpu.scanline,
-            0xFF47 => panic!("0xFF47 (bg_palette) is WRITE ONLY"),
-            0xFF4A => self.gpu.windowy,
-            0xFF4B => self.gpu.windowx,
+            0x0000..=0x00FF if self.in_bios == 0 => self.bootrom[address as usize],
+            0x0000..=0x7FFF => self.cheats.patch_rom(address, self.cartridge.read(address)),
+            0xA000..=0xBFFF => self.cartridge.read(address),
+            VRAM_START..=VRAM_END | OAM_START..=OAM_END => self.gpu.read(address),
+            WRAM_START..=ECHO_END => self.wram.read(address),
+            UNUSABLE_START..=UNUSABLE_END => self.model.unusable_read(address),
+            IO_START..=IO_END => self.io_read(address),
+            HRAM_START..=HRAM_END => self.hram.read(address),
             0xffff => self.int_enabled,
-            0xff0f => self.int_flags,
-            0xff00 => match self.select {
-                Select::Buttons => self.keypresses,
-                Select::Directions => self.directions,
-                Select::None => 0xFF,
-            },
-            // 0xFFFF => &self.gpu.,
-            // 0xFF01 => {println!("R: ACC SERIAL TRANSFER DATA"); &self.memory[ias usize]},
-            // 0xFF02 => {println!("R: ACC SERIAL TRANSFER DATA FLGS"); &self.memory[i as usize]},
-            VRAM_START..=VRAM_END => self.gpu[address],
-            OAM_START..=OAM_END => self.gpu.oam[address as usize - OAM_START],
-            _ => self.memory[address as usize],
+            _ => unreachable!(),
         }
     }
     fn write(&mut self, address: u16, value: u8) {
+        if let Some((_, device)) = self
+            .devices
+            .iter_mut()
+            .find(|(range, _)| range.contains(&address))
+        {
+            return device.write(address, value, &mut self.int_flags);
+        }
         match address as usize {
-            0x0000..=0x0100 if self.in_bios == 0 => panic!(),
-            timer::DIV => {self.timer.update_internal(&mut self.int_flags, 0) },
-            timer::TAC => {
-                self.timer.tac = 0b1111_1000 | value
-            },
-            timer::TIMA => self.timer.tima = value,
-            timer::TMA => self.timer.tma = value,
-            0xff40 => self.gpu.lcdc = value,
-            0xff41 => self.gpu.lcdstat = value,
-            0xff42 => self.gpu.scrolly = value,
-            0xff43 => self.gpu.scrollx = value,
-            0xff44 => self.gpu.scanline = value,
-            0xff46 => {
-                //OAM Transfer request
-                let value = value as u16;
-                if value <= 0xF1 {
-                    let range = ((value << 8) as usize)..=((value << 8) as usize | 0xFF);
-                    self.gpu.oam.copy_from_slice(&self.memory[range]);
-                    self.memory[address as usize] = value as u8;
-                }
+            0x0000..=0x00FF if self.in_bios == 0 => panic!(),
+            0x0000..=0x7FFF | 0xA000..=0xBFFF => self.cartridge.write(address, value),
+            VRAM_START..=VRAM_END | OAM_START..=OAM_END => {
+                self.gpu.write(address, value, &mut self.int_flags)
             }
-            0xff47 => self.gpu.bgrdpal = value,
-            0xff48 => self.gpu.obj0pal = value,
-            0xff49 => self.gpu.obj1pal = value,
-            0xff4a => self.gpu.windowy = value,
-            0xff4b => self.gpu.windowx = value,
+            WRAM_START..=ECHO_END => self.wram.write(address, value, &mut self.int_flags),
+            UNUSABLE_START..=UNUSABLE_END => {}
+            IO_START..=IO_END => self.io_write(address, value),
+            HRAM_START..=HRAM_END => self.hram.write(address, value, &mut self.int_flags),
             0xffff => self.int_enabled = value,
-            0xff0f => {
-                self.int_flags |= value;
-            }
-            0xff50 => {
-                if value != 0 && !self.rom_start_signal {
-                    self.rom_start_signal = true;
-                }
-                self.in_bios = value
-            }
-            0xff80 => {
-                self.memory[address as usize] = value;
-            }
-            0xff00 => {
-                self.select = match value & 0xF0 {
-                    0b0001_0000 => Select::Buttons,
-                    0b0010_0000 => Select::Directions,
-                    0b0011_0000 => Select::None,
-                    _ => Select::None,
-                }
-            }
-            0xff01 => {
-                self.memory[address as usize] = value;
-            }
-            0xff02 => {
-                if value == 0x81 {
-                    self.io.push(char::from(self.memory[0xff01]));
-                }
-                self.memory[address as usize] = value;
-            }
-            VRAM_START..=VRAM_END => self.gpu.vram[address as usize - VRAM_START] = value,
-            OAM_START..=OAM_END => self.gpu.oam[address as usize - OAM_START] = value,
-            _ => {
-                if address >= 0x8000 {
-                    self.memory[address as usize] = value
-                }
-            }
+            _ => unreachable!(),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{cell::RefCell, rc::Rc};
+
+    #[test]
+    fn echo_ram() {
+        let mut bus = Bus::new(vec![], None);
+        bus.write(0xC123, 0x42);
+        assert_eq!(bus.read(0xE123), 0x42);
+        bus.write(0xFDFF, 0x24);
+        assert_eq!(bus.read(0xDDFF), 0x24);
+        bus.write(0xFF80, 0x11);
+        assert_eq!(bus.read(0xFF80), 0x11);
+    }
+
+    #[test]
+    fn unusable_region() {
+        let mut bus = Bus::new(vec![], None);
+        bus.write(0xFEA0, 0x42);
+        assert_eq!(bus.read(0xFEA0), 0x00);
+        bus.model = Model::Cgb;
+        assert_eq!(bus.read(0xFEB3), 0xBB);
+        assert_eq!(bus.read(0xFEFF), 0xFF);
+    }
+
+    #[test]
+    fn io_masks() {
+        let mut bus = Bus::new(vec![], None);
+        // Unmapped registers read 0xFF and ignore writes.
+        bus.write(0xFF03, 0x00);
+        assert_eq!(bus.read(0xFF03), 0xFF);
+        assert_eq!(bus.read(0xFF4D), 0xFF);
+        // Unused bits read as 1.
+        bus.write(0xFF0F, 0x00);
+        assert_eq!(bus.read(0xFF0F), 0xE0);
+        bus.write(0xFF26, 0x00);
+        assert_eq!(bus.read(0xFF26), 0x70);
+        // Write only registers read as 0xFF.
+        bus.write(0xFF13, 0x12);
+        assert_eq!(bus.read(0xFF13), 0xFF);
+        // BGP is readable.
+        bus.write(0xFF47, 0xE4);
+        assert_eq!(bus.read(0xFF47), 0xE4);
+        // STAT mode bits and LY are read only.
+        bus.write(0xFF41, 0xFF);
+        assert_eq!(bus.read(0xFF41), 0xF8);
+        bus.write(0xFF44, 0x12);
+        assert_eq!(bus.read(0xFF44), 0x00);
+    }
+
+    struct TestDevice {
+        writes: Rc<RefCell<Vec<(u16, u8)>>>,
+    }
+
+    impl Device for TestDevice {
+        fn read(&self, address: u16) -> u8 {
+            address as u8
+        }
+        fn write(&mut self, address: u16, value: u8, int_flags: &mut u8) {
+            self.writes.borrow_mut().push((address, value));
+            *int_flags |= crate::cpu::SERIAL;
+        }
+    }
+
+    #[test]
+    fn mapped_device() {
+        let mut bus = Bus::new(vec![], None);
+        let writes = Rc::new(RefCell::new(vec![]));
+        bus.map_device(
+            0xFF01..=0xFF02,
+            Box::new(TestDevice {
+                writes: writes.clone(),
+            }),
+        );
+        assert_eq!(bus.read(0xFF01), 0x01);
+        assert_eq!(bus.read(0xFF03), 0xFF);
+        bus.write(0xFF02, 0x81);
+        assert_eq!(*writes.borrow(), vec![(0xFF02, 0x81)]);
+        assert_eq!(bus.int_flags, crate::cpu::SERIAL);
+        assert_eq!(bus.io, "");
+    }
+}
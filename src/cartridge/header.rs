@@ -0,0 +1,219 @@
+use std::{error::Error, fmt::Display};
+
+use super::{ram_size, CARTRIDGE_TYPE, RAM_SIZE, ROM_BANK_SIZE};
+
+// https://gbdev.io/pandocs/The_Cartridge_Header.html
+pub const HEADER_END: usize = 0x150;
+pub const TITLE: usize = 0x134;
+pub const MANUFACTURER: usize = 0x13F;
+pub const CGB_FLAG: usize = 0x143;
+pub const NEW_LICENSEE: usize = 0x144;
+pub const SGB_FLAG: usize = 0x146;
+pub const ROM_SIZE: usize = 0x148;
+pub const DESTINATION: usize = 0x14A;
+pub const OLD_LICENSEE: usize = 0x14B;
+pub const VERSION: usize = 0x14C;
+pub const HEADER_CHECKSUM: usize = 0x14D;
+pub const GLOBAL_CHECKSUM: usize = 0x14E;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CgbSupport {
+    None,
+    Supported, // 0x80, works on DMG too
+    Only,      // 0xC0
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Licensee {
+    Old(u8),
+    New(String), // Used when the old code is 0x33
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Header {
+    pub title: String,
+    pub manufacturer: Option<String>,
+    pub licensee: Licensee,
+    pub cgb: CgbSupport,
+    pub sgb: bool,
+    pub cartridge_type: u8,
+    pub rom_size: usize,
+    pub ram_size: usize,
+    pub japanese: bool,
+    pub version: u8,
+    pub header_checksum: u8,
+    pub global_checksum: u16,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum HeaderError {
+    // The file is shorter than the header, or than the ROM size it declares.
+    Truncated { expected: usize, actual: usize },
+    UnknownRomSize(u8),
+    UnknownRamSize(u8),
+    HeaderChecksum { expected: u8, actual: u8 },
+    GlobalChecksum { expected: u16, actual: u16 },
+}
+
+impl Display for HeaderError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            HeaderError::Truncated { expected, actual } => write!(
+                f,
+                "ROM is truncated: expected {} bytes, found {}",
+                expected, actual
+            ),
+            HeaderError::UnknownRomSize(v) => write!(f, "Unknown ROM size code {:02x}", v),
+            HeaderError::UnknownRamSize(v) => write!(f, "Unknown RAM size code {:02x}", v),
+            HeaderError::HeaderChecksum { expected, actual } => write!(
+                f,
+                "Header checksum mismatch: header says {:02x}, computed {:02x}",
+                expected, actual
+            ),
+            HeaderError::GlobalChecksum { expected, actual } => write!(
+                f,
+                "Global checksum mismatch: header says {:04x}, computed {:04x}",
+                expected, actual
+            ),
+        }
+    }
+}
+
+impl Error for HeaderError {}
+
+// ROM size in bytes for the header byte at 0x148.
+pub fn rom_size(value: u8) -> Option<usize> {
+    match value {
+        0x00..=0x08 => Some((ROM_BANK_SIZE * 2) << value),
+        0x52 => Some(ROM_BANK_SIZE * 72),
+        0x53 => Some(ROM_BANK_SIZE * 80),
+        0x54 => Some(ROM_BANK_SIZE * 96),
+        _ => None,
+    }
+}
+
+// The boot ROM refuses to start a cartridge that fails this check.
+pub fn header_checksum(rom: &[u8]) -> u8 {
+    rom[TITLE..HEADER_CHECKSUM]
+        .iter()
+        .fold(0u8, |x, b| x.wrapping_sub(*b).wrapping_sub(1))
+}
+
+// Sum of every byte in the ROM except the checksum itself. Never checked by hardware.
+pub fn global_checksum(rom: &[u8]) -> u16 {
+    rom.iter()
+        .enumerate()
+        .filter(|(i, _)| *i != GLOBAL_CHECKSUM && *i != GLOBAL_CHECKSUM + 1)
+        .fold(0u16, |sum, (_, b)| sum.wrapping_add(*b as u16))
+}
+
+fn ascii(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .take_while(|b| **b != 0)
+        .map(|b| {
+            if b.is_ascii_graphic() || *b == b' ' {
+                *b as char
+            } else {
+                '?'
+            }
+        })
+        .collect::<String>()
+        .trim_end()
+        .to_string()
+}
+
+impl Header {
+    pub fn parse(rom: &[u8]) -> Result<Header, HeaderError> {
+        if rom.len() < HEADER_END {
+            return Err(HeaderError::Truncated {
+                expected: HEADER_END,
+                actual: rom.len(),
+            });
+        }
+        let header_checksum = header_checksum(rom);
+        if header_checksum != rom[HEADER_CHECKSUM] {
+            return Err(HeaderError::HeaderChecksum {
+                expected: rom[HEADER_CHECKSUM],
+                actual: header_checksum,
+            });
+        }
+        let rom_size = rom_size(rom[ROM_SIZE]).ok_or(HeaderError::UnknownRomSize(rom[ROM_SIZE]))?;
+        if rom.len() < rom_size {
+            return Err(HeaderError::Truncated {
+                expected: rom_size,
+                actual: rom.len(),
+            });
+        }
+        if rom[RAM_SIZE] > 0x05 {
+            return Err(HeaderError::UnknownRamSize(rom[RAM_SIZE]));
+        }
+
+        let cgb = match rom[CGB_FLAG] {
+            0xC0 => CgbSupport::Only,
+            0x80 => CgbSupport::Supported,
+            _ => CgbSupport::None,
+        };
+        // Newer cartridges give up the end of the title for the manufacturer code and CGB flag.
+        let (title, manufacturer) = if cgb == CgbSupport::None {
+            (ascii(&rom[TITLE..=CGB_FLAG]), None)
+        } else {
+            let code = &rom[MANUFACTURER..CGB_FLAG];
+            if code
+                .iter()
+                .all(|b| b.is_ascii_uppercase() || b.is_ascii_digit())
+            {
+                (ascii(&rom[TITLE..MANUFACTURER]), Some(ascii(code)))
+            } else {
+                (ascii(&rom[TITLE..CGB_FLAG]), None)
+            }
+        };
+        let licensee = match rom[OLD_LICENSEE] {
+            0x33 => Licensee::New(ascii(&rom[NEW_LICENSEE..NEW_LICENSEE + 2])),
+            code => Licensee::Old(code),
+        };
+
+        Ok(Header {
+            title,
+            manufacturer,
+            licensee,
+            cgb,
+            sgb: rom[SGB_FLAG] == 0x03,
+            cartridge_type: rom[CARTRIDGE_TYPE],
+            rom_size,
+            ram_size: ram_size(rom[RAM_SIZE]),
+            japanese: rom[DESTINATION] == 0x00,
+            version: rom[VERSION],
+            header_checksum,
+            global_checksum: u16::from_be_bytes([rom[GLOBAL_CHECKSUM], rom[GLOBAL_CHECKSUM + 1]]),
+        })
+    }
+
+    pub fn verify_global_checksum(&self, rom: &[u8]) -> Result<(), HeaderError> {
+        let actual = global_checksum(rom);
+        if actual != self.global_checksum {
+            return Err(HeaderError::GlobalChecksum {
+                expected: self.global_checksum,
+                actual,
+            });
+        }
+        Ok(())
+    }
+}
+
+impl Display for Header {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_fmt(format_args!(
+            r#"Title: {}
+Type: {:02x}, ROM: {}KiB, RAM: {}KiB
+CGB: {:?}, SGB: {}, Licensee: {:?}"#,
+            self.title,
+            self.cartridge_type,
+            self.rom_size / 1024,
+            self.ram_size / 1024,
+            self.cgb,
+            self.sgb,
+            self.licensee,
+        ))
+    }
+}
@@ -0,0 +1,102 @@
+use crate::bus::Memory;
+
+use super::{ram_bank_index, rom_bank_read, Cartridge};
+
+// https://gbdev.io/pandocs/MBC1.html
+pub struct Mbc1 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    ram_enabled: bool,
+    bank1: u8, // 5 bit ROM bank register (0x2000-0x3FFF)
+    bank2: u8, // 2 bit RAM bank / upper ROM bank register (0x4000-0x5FFF)
+    mode: u8,  // Banking mode select (0x6000-0x7FFF)
+}
+
+impl Mbc1 {
+    pub fn new(rom: Vec<u8>, ram_size: usize) -> Self {
+        Self {
+            rom,
+            ram: vec![0; ram_size],
+            ram_enabled: false,
+            bank1: 1,
+            bank2: 0,
+            mode: 0,
+        }
+    }
+
+    fn low_rom_bank(&self) -> usize {
+        if self.mode == 1 {
+            (self.bank2 as usize) << 5
+        } else {
+            0
+        }
+    }
+
+    fn high_rom_bank(&self) -> usize {
+        ((self.bank2 as usize) << 5) | self.bank1 as usize
+    }
+
+    fn ram_bank(&self) -> usize {
+        if self.mode == 1 {
+            self.bank2 as usize
+        } else {
+            0
+        }
+    }
+}
+
+impl Memory for Mbc1 {
+    fn read(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x3FFF => rom_bank_read(&self.rom, self.low_rom_bank(), address),
+            0x4000..=0x7FFF => rom_bank_read(&self.rom, self.high_rom_bank(), address),
+            0xA000..=0xBFFF if self.ram_enabled => {
+                match ram_bank_index(&self.ram, self.ram_bank(), address) {
+                    Some(i) => self.ram[i],
+                    None => 0xFF,
+                }
+            }
+            _ => 0xFF,
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x3FFF => {
+                self.bank1 = value & 0x1F;
+                if self.bank1 == 0 {
+                    self.bank1 = 1;
+                }
+            }
+            0x4000..=0x5FFF => self.bank2 = value & 0b11,
+            0x6000..=0x7FFF => self.mode = value & 0b1,
+            0xA000..=0xBFFF if self.ram_enabled => {
+                if let Some(i) = ram_bank_index(&self.ram, self.ram_bank(), address) {
+                    self.ram[i] = value;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Cartridge for Mbc1 {
+    fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn load_ram(&mut self, data: &[u8]) {
+        self.ram[..data.len()].copy_from_slice(data);
+    }
+
+    fn poke_ram(&mut self, bank: usize, address: u16, value: u8) {
+        if let Some(i) = ram_bank_index(&self.ram, bank, address) {
+            self.ram[i] = value;
+        }
+    }
+}
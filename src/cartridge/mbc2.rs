@@ -0,0 +1,79 @@
+use crate::bus::Memory;
+
+use super::{rom_bank_read, Cartridge};
+
+// 512 half-bytes of RAM live inside the mapper itself.
+pub const MBC2_RAM_SIZE: usize = 0x200;
+
+// https://gbdev.io/pandocs/MBC2.html
+pub struct Mbc2 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    ram_enabled: bool,
+    rom_bank: u8, // 4 bits
+}
+
+impl Mbc2 {
+    pub fn new(rom: Vec<u8>) -> Self {
+        Self {
+            rom,
+            ram: vec![0; MBC2_RAM_SIZE],
+            ram_enabled: false,
+            rom_bank: 1,
+        }
+    }
+}
+
+impl Memory for Mbc2 {
+    fn read(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x3FFF => rom_bank_read(&self.rom, 0, address),
+            0x4000..=0x7FFF => rom_bank_read(&self.rom, self.rom_bank as usize, address),
+            // Only the low nibble is stored, the upper bits float high.
+            // The 512 bytes repeat across the whole external RAM range.
+            0xA000..=0xBFFF if self.ram_enabled => {
+                0xF0 | self.ram[address as usize % MBC2_RAM_SIZE]
+            }
+            _ => 0xFF,
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        match address {
+            // Bit 8 of the address picks the register:
+            // clear for RAM enable, set for the ROM bank number.
+            0x0000..=0x3FFF if address & 0x100 == 0 => self.ram_enabled = value & 0x0F == 0x0A,
+            0x0000..=0x3FFF => {
+                self.rom_bank = value & 0x0F;
+                if self.rom_bank == 0 {
+                    self.rom_bank = 1;
+                }
+            }
+            0xA000..=0xBFFF if self.ram_enabled => {
+                self.ram[address as usize % MBC2_RAM_SIZE] = value & 0x0F;
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Cartridge for Mbc2 {
+    fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn load_ram(&mut self, data: &[u8]) {
+        for (nibble, byte) in self.ram.iter_mut().zip(data) {
+            *nibble = byte & 0x0F;
+        }
+    }
+
+    // There's only the one bank.
+    fn poke_ram(&mut self, _bank: usize, address: u16, value: u8) {
+        self.ram[address as usize % MBC2_RAM_SIZE] = value & 0x0F;
+    }
+}
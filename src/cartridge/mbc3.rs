@@ -0,0 +1,296 @@
+use std::convert::TryInto;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::bus::Memory;
+use crate::constants::GB_CYCLE_SPEED;
+
+use super::{ram_bank_index, rom_bank_read, Cartridge};
+
+// Bus cycles (M-cycles) per emulated second.
+pub const RTC_CYCLES_PER_SECOND: usize = GB_CYCLE_SPEED / 4;
+
+const DAY_HIGH: u8 = 0b0000_0001;
+const HALT: u8 = 0b0100_0000;
+const DAY_CARRY: u8 = 0b1000_0000;
+
+// The RTC footer used by BGB and VBA-M: the live and latched registers as
+// 32 bit values, followed by a unix timestamp (64 bit, older files use 32).
+pub const RTC_SAVE_SIZE: usize = 44;
+
+// Where the RTC gets its notion of elapsed time from.
+// Cycles keeps runs deterministic, Host follows the wall clock like a real cartridge.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RtcClock {
+    Host,
+    Cycles,
+}
+
+// https://gbdev.io/pandocs/MBC3.html#the-clock-counter-registers
+#[derive(Debug, Clone)]
+pub struct Rtc {
+    pub clock: RtcClock,
+    pub seconds: u8,
+    pub minutes: u8,
+    pub hours: u8,
+    pub days: u16, // 9 bits
+    pub halt: bool,
+    pub carry: bool,
+    pub latched: [u8; 5],
+    cycles: usize,
+    last_sync: SystemTime,
+}
+
+impl Rtc {
+    pub fn new(clock: RtcClock) -> Self {
+        Self {
+            clock,
+            seconds: 0,
+            minutes: 0,
+            hours: 0,
+            days: 0,
+            halt: false,
+            carry: false,
+            latched: [0; 5],
+            cycles: 0,
+            last_sync: SystemTime::now(),
+        }
+    }
+
+    // Moves the counter forward by `secs`, setting the day carry bit when the 9 bit
+    // day counter overflows.
+    pub fn advance(&mut self, secs: u64) {
+        if self.halt || secs == 0 {
+            return;
+        }
+        let total = self.seconds as u64
+            + self.minutes as u64 * 60
+            + self.hours as u64 * 3600
+            + self.days as u64 * 86400
+            + secs;
+        self.seconds = (total % 60) as u8;
+        self.minutes = (total / 60 % 60) as u8;
+        self.hours = (total / 3600 % 24) as u8;
+        let days = total / 86400;
+        if days > 0x1FF {
+            self.carry = true;
+        }
+        self.days = (days & 0x1FF) as u16;
+    }
+
+    // Catches up with the host clock. Only whole seconds are consumed so the
+    // remainder carries over to the next sync.
+    pub fn sync(&mut self) {
+        if self.clock != RtcClock::Host {
+            return;
+        }
+        let now = SystemTime::now();
+        if self.halt {
+            self.last_sync = now;
+            return;
+        }
+        if let Ok(elapsed) = now.duration_since(self.last_sync) {
+            let secs = elapsed.as_secs();
+            self.advance(secs);
+            self.last_sync += Duration::from_secs(secs);
+        }
+    }
+
+    pub fn tick(&mut self) {
+        if self.clock != RtcClock::Cycles || self.halt {
+            return;
+        }
+        self.cycles += 1;
+        if self.cycles >= RTC_CYCLES_PER_SECOND {
+            self.cycles = 0;
+            self.advance(1);
+        }
+    }
+
+    pub fn latch(&mut self) {
+        self.sync();
+        self.latched = self.registers();
+    }
+
+    pub fn registers(&self) -> [u8; 5] {
+        let [day_lo, day_hi] = self.days.to_le_bytes();
+        let mut control = day_hi & DAY_HIGH;
+        if self.halt {
+            control |= HALT;
+        }
+        if self.carry {
+            control |= DAY_CARRY;
+        }
+        [self.seconds, self.minutes, self.hours, day_lo, control]
+    }
+
+    pub fn save(&mut self) -> Vec<u8> {
+        self.sync();
+        let mut data = Vec::with_capacity(48);
+        for value in self.registers().iter().chain(self.latched.iter()) {
+            data.extend_from_slice(&(*value as u32).to_le_bytes());
+        }
+        let time = match self.clock {
+            RtcClock::Host => self.last_sync,
+            RtcClock::Cycles => SystemTime::now(),
+        };
+        let timestamp = time
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0);
+        data.extend_from_slice(&timestamp.to_le_bytes());
+        data
+    }
+
+    // Restores a footer written by `save`. With the host clock the time spent
+    // switched off is caught up on the next sync.
+    pub fn load(&mut self, data: &[u8]) {
+        let values: Vec<u8> = data[..40].chunks_exact(4).map(|v| v[0]).collect();
+        self.seconds = values[0] & 0x3F;
+        self.minutes = values[1] & 0x3F;
+        self.hours = values[2] & 0x1F;
+        self.days = u16::from_le_bytes([values[3], values[4] & DAY_HIGH]);
+        self.halt = values[4] & HALT != 0;
+        self.carry = values[4] & DAY_CARRY != 0;
+        self.latched.copy_from_slice(&values[5..10]);
+        let timestamp = match data.get(40..48) {
+            Some(t) => u64::from_le_bytes(t.try_into().unwrap()),
+            None => u32::from_le_bytes(data[40..44].try_into().unwrap()) as u64,
+        };
+        if self.clock == RtcClock::Host {
+            self.last_sync = UNIX_EPOCH + Duration::from_secs(timestamp);
+        }
+    }
+
+    // Reads one of the latched registers, 0x08-0x0C.
+    pub fn read(&self, register: u8) -> u8 {
+        self.latched[(register - 0x08) as usize]
+    }
+
+    pub fn write(&mut self, register: u8, value: u8) {
+        self.sync();
+        match register {
+            0x08 => {
+                self.seconds = value & 0x3F;
+                // Writing the seconds resets the sub-second divider.
+                self.cycles = 0;
+                self.last_sync = SystemTime::now();
+            }
+            0x09 => self.minutes = value & 0x3F,
+            0x0A => self.hours = value & 0x1F,
+            0x0B => self.days = (self.days & 0x100) | value as u16,
+            0x0C => {
+                self.days = (self.days & 0xFF) | (((value & DAY_HIGH) as u16) << 8);
+                self.halt = value & HALT != 0;
+                self.carry = value & DAY_CARRY != 0;
+            }
+            _ => unreachable!(),
+        }
+    }
+}
+
+// https://gbdev.io/pandocs/MBC3.html
+pub struct Mbc3 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    rtc: Option<Rtc>,
+    ram_enabled: bool,
+    rom_bank: u8,
+    ram_select: u8, // RAM bank 0x00-0x07, RTC register 0x08-0x0C
+    latch: u8,
+}
+
+impl Mbc3 {
+    pub fn new(rom: Vec<u8>, ram_size: usize, rtc: Option<Rtc>) -> Self {
+        Self {
+            rom,
+            ram: vec![0; ram_size],
+            rtc,
+            ram_enabled: false,
+            rom_bank: 1,
+            ram_select: 0,
+            latch: 0xFF,
+        }
+    }
+}
+
+impl Memory for Mbc3 {
+    fn read(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x3FFF => rom_bank_read(&self.rom, 0, address),
+            0x4000..=0x7FFF => rom_bank_read(&self.rom, self.rom_bank as usize, address),
+            0xA000..=0xBFFF if self.ram_enabled => match (self.ram_select, &self.rtc) {
+                (0x08..=0x0C, Some(rtc)) => rtc.read(self.ram_select),
+                (0x00..=0x07, _) => {
+                    match ram_bank_index(&self.ram, self.ram_select as usize, address) {
+                        Some(i) => self.ram[i],
+                        None => 0xFF,
+                    }
+                }
+                _ => 0xFF,
+            },
+            _ => 0xFF,
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = value & 0x0F == 0x0A,
+            0x2000..=0x3FFF => {
+                self.rom_bank = value & 0x7F;
+                if self.rom_bank == 0 {
+                    self.rom_bank = 1;
+                }
+            }
+            0x4000..=0x5FFF => self.ram_select = value,
+            0x6000..=0x7FFF => {
+                // Writing 0x00 then 0x01 copies the live clock into the latched registers.
+                if self.latch == 0x00 && value == 0x01 {
+                    if let Some(rtc) = &mut self.rtc {
+                        rtc.latch();
+                    }
+                }
+                self.latch = value;
+            }
+            0xA000..=0xBFFF if self.ram_enabled => match (self.ram_select, &mut self.rtc) {
+                (0x08..=0x0C, Some(rtc)) => rtc.write(self.ram_select, value),
+                (0x00..=0x07, _) => {
+                    if let Some(i) = ram_bank_index(&self.ram, self.ram_select as usize, address) {
+                        self.ram[i] = value;
+                    }
+                }
+                _ => {}
+            },
+            _ => {}
+        }
+    }
+}
+
+impl Cartridge for Mbc3 {
+    fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn load_ram(&mut self, data: &[u8]) {
+        self.ram[..data.len()].copy_from_slice(data);
+    }
+
+    fn poke_ram(&mut self, bank: usize, address: u16, value: u8) {
+        if let Some(i) = ram_bank_index(&self.ram, bank, address) {
+            self.ram[i] = value;
+        }
+    }
+
+    fn tick(&mut self) {
+        if let Some(rtc) = &mut self.rtc {
+            rtc.tick();
+        }
+    }
+
+    fn rtc(&mut self) -> Option<&mut Rtc> {
+        self.rtc.as_mut()
+    }
+}
@@ -0,0 +1,96 @@
+use crate::bus::Memory;
+
+use super::{ram_bank_index, rom_bank_read, Cartridge};
+
+// On rumble cartridges bit 3 of the RAM bank register drives the motor.
+const RUMBLE_MOTOR: u8 = 0b1000;
+
+// https://gbdev.io/pandocs/MBC5.html
+pub struct Mbc5 {
+    rom: Vec<u8>,
+    ram: Vec<u8>,
+    ram_enabled: bool,
+    rom_bank: u16, // 9 bits
+    ram_bank: u8,  // 4 bits, 3 on rumble cartridges
+    has_rumble: bool,
+    motor: bool,
+}
+
+impl Mbc5 {
+    pub fn new(rom: Vec<u8>, ram_size: usize, has_rumble: bool) -> Self {
+        Self {
+            rom,
+            ram: vec![0; ram_size],
+            ram_enabled: false,
+            rom_bank: 1,
+            ram_bank: 0,
+            has_rumble,
+            motor: false,
+        }
+    }
+}
+
+impl Memory for Mbc5 {
+    fn read(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x3FFF => rom_bank_read(&self.rom, 0, address),
+            0x4000..=0x7FFF => rom_bank_read(&self.rom, self.rom_bank as usize, address),
+            0xA000..=0xBFFF if self.ram_enabled => {
+                match ram_bank_index(&self.ram, self.ram_bank as usize, address) {
+                    Some(i) => self.ram[i],
+                    None => 0xFF,
+                }
+            }
+            _ => 0xFF,
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8) {
+        match address {
+            0x0000..=0x1FFF => self.ram_enabled = value == 0x0A,
+            // Unlike MBC1, bank 0 can be mapped into 0x4000-0x7FFF.
+            0x2000..=0x2FFF => self.rom_bank = (self.rom_bank & 0x100) | value as u16,
+            0x3000..=0x3FFF => {
+                self.rom_bank = (self.rom_bank & 0xFF) | (((value & 0b1) as u16) << 8)
+            }
+            0x4000..=0x5FFF => {
+                if self.has_rumble {
+                    self.motor = value & RUMBLE_MOTOR != 0;
+                    self.ram_bank = value & 0b0111;
+                } else {
+                    self.ram_bank = value & 0b1111;
+                }
+            }
+            0xA000..=0xBFFF if self.ram_enabled => {
+                if let Some(i) = ram_bank_index(&self.ram, self.ram_bank as usize, address) {
+                    self.ram[i] = value;
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+impl Cartridge for Mbc5 {
+    fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+
+    fn ram(&self) -> &[u8] {
+        &self.ram
+    }
+
+    fn load_ram(&mut self, data: &[u8]) {
+        self.ram[..data.len()].copy_from_slice(data);
+    }
+
+    fn poke_ram(&mut self, bank: usize, address: u16, value: u8) {
+        if let Some(i) = ram_bank_index(&self.ram, bank, address) {
+            self.ram[i] = value;
+        }
+    }
+
+    fn rumble(&self) -> bool {
+        self.motor
+    }
+}
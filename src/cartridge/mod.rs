@@ -0,0 +1,155 @@
+pub mod header;
+pub mod mbc1;
+pub mod mbc2;
+pub mod mbc3;
+pub mod mbc5;
+
+use crate::bus::Memory;
+use mbc1::Mbc1;
+use mbc2::Mbc2;
+use mbc3::{Mbc3, Rtc, RtcClock, RTC_SAVE_SIZE};
+use mbc5::Mbc5;
+
+pub const ROM_BANK_SIZE: usize = 0x4000;
+pub const RAM_BANK_SIZE: usize = 0x2000;
+
+// Header offsets
+pub const CARTRIDGE_TYPE: usize = 0x147;
+pub const RAM_SIZE: usize = 0x149;
+
+// A cartridge sits behind the bus for 0x0000-0x7FFF (ROM + mapper registers)
+// and 0xA000-0xBFFF (external RAM). Addresses passed in are the full bus address.
+pub trait Cartridge: Memory {
+    fn rom(&self) -> &[u8];
+
+    // Called once per bus cycle, for mappers with their own clock.
+    fn tick(&mut self) {}
+
+    fn rtc(&mut self) -> Option<&mut Rtc> {
+        None
+    }
+
+    // State of the rumble motor, for cartridges that have one.
+    fn rumble(&self) -> bool {
+        false
+    }
+
+    fn ram(&self) -> &[u8] {
+        &[]
+    }
+
+    fn load_ram(&mut self, _data: &[u8]) {}
+
+    // Writes straight to external RAM `bank`, mapped in and enabled or not.
+    fn poke_ram(&mut self, _bank: usize, _address: u16, _value: u8) {}
+
+    fn has_battery(&self) -> bool {
+        has_battery(self.rom().get(CARTRIDGE_TYPE).copied().unwrap_or(0))
+    }
+
+    // Contents of a .sav file: the raw RAM dump, followed by the RTC footer for MBC3.
+    fn save_data(&mut self) -> Vec<u8> {
+        let mut data = self.ram().to_vec();
+        if let Some(rtc) = self.rtc() {
+            data.extend_from_slice(&rtc.save());
+        }
+        data
+    }
+
+    fn load_save_data(&mut self, data: &[u8]) {
+        let ram_size = self.ram().len().min(data.len());
+        self.load_ram(&data[..ram_size]);
+        if let Some(rtc) = self.rtc() {
+            if data.len() >= ram_size + RTC_SAVE_SIZE {
+                rtc.load(&data[ram_size..]);
+            }
+        }
+    }
+}
+
+// Plain 32KiB cartridge, no mapper.
+pub struct RomOnly {
+    rom: Vec<u8>,
+}
+
+impl RomOnly {
+    pub fn new(rom: Vec<u8>) -> Self {
+        Self { rom }
+    }
+}
+
+impl Memory for RomOnly {
+    fn read(&self, address: u16) -> u8 {
+        match address {
+            0x0000..=0x7FFF => self.rom.get(address as usize).copied().unwrap_or(0xFF),
+            _ => 0xFF,
+        }
+    }
+    fn write(&mut self, _address: u16, _value: u8) {}
+}
+
+impl Cartridge for RomOnly {
+    fn rom(&self) -> &[u8] {
+        &self.rom
+    }
+}
+
+// Picks a mapper using the cartridge type byte at 0x147.
+pub fn from_rom(rom: Vec<u8>) -> Box<dyn Cartridge> {
+    let cartridge_type = rom.get(CARTRIDGE_TYPE).copied().unwrap_or(0);
+    let ram_size = ram_size(rom.get(RAM_SIZE).copied().unwrap_or(0));
+    match cartridge_type {
+        0x00 => Box::new(RomOnly::new(rom)),
+        0x01..=0x03 => Box::new(Mbc1::new(rom, ram_size)),
+        0x05..=0x06 => Box::new(Mbc2::new(rom)),
+        0x0F..=0x10 => Box::new(Mbc3::new(rom, ram_size, Some(Rtc::new(RtcClock::Host)))),
+        0x11..=0x13 => Box::new(Mbc3::new(rom, ram_size, None)),
+        0x19..=0x1B => Box::new(Mbc5::new(rom, ram_size, false)),
+        0x1C..=0x1E => Box::new(Mbc5::new(rom, ram_size, true)),
+        _ => {
+            println!(
+                "Unsupported cartridge type {:02x}, running as ROM only.",
+                cartridge_type
+            );
+            Box::new(RomOnly::new(rom))
+        }
+    }
+}
+
+pub fn has_battery(cartridge_type: u8) -> bool {
+    matches!(
+        cartridge_type,
+        0x03 | 0x06 | 0x09 | 0x0D | 0x0F | 0x10 | 0x13 | 0x1B | 0x1E
+    )
+}
+
+// External RAM size in bytes for the header byte at 0x149.
+pub fn ram_size(value: u8) -> usize {
+    match value {
+        0x01 => 0x800,
+        0x02 => RAM_BANK_SIZE,
+        0x03 => RAM_BANK_SIZE * 4,
+        0x04 => RAM_BANK_SIZE * 16,
+        0x05 => RAM_BANK_SIZE * 8,
+        _ => 0,
+    }
+}
+
+// Reads `address` (0x0000-0x3FFF relative) from `bank`, wrapping the bank
+// number to the number of banks present like the unconnected address lines would.
+pub fn rom_bank_read(rom: &[u8], bank: usize, address: u16) -> u8 {
+    let banks = (rom.len() / ROM_BANK_SIZE).max(1);
+    let index = (bank % banks) * ROM_BANK_SIZE + (address as usize & 0x3FFF);
+    rom.get(index).copied().unwrap_or(0xFF)
+}
+
+pub fn ram_bank_index(ram: &[u8], bank: usize, address: u16) -> Option<usize> {
+    if ram.is_empty() {
+        return None;
+    }
+    let index = bank * RAM_BANK_SIZE + (address as usize & 0x1FFF);
+    Some(index % ram.len())
+}
+
+#[cfg(test)]
+mod test;
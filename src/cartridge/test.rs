@@ -0,0 +1,246 @@
+use super::header::*;
+use super::*;
+
+// Builds a ROM where the first byte of every bank holds its bank number.
+fn banked_rom(cartridge_type: u8, banks: usize, ram_size: u8) -> Vec<u8> {
+    let mut rom = vec![0; banks * ROM_BANK_SIZE];
+    for bank in 0..banks {
+        rom[bank * ROM_BANK_SIZE] = bank as u8;
+    }
+    rom[CARTRIDGE_TYPE] = cartridge_type;
+    rom[RAM_SIZE] = ram_size;
+    rom
+}
+
+#[test]
+fn rom_only() {
+    let mut cart = from_rom(banked_rom(0x00, 2, 0));
+    assert_eq!(cart.read(0x4000), 1);
+    cart.write(0x2000, 0x05);
+    assert_eq!(cart.read(0x4000), 1);
+    assert_eq!(cart.read(0xA000), 0xFF);
+}
+
+#[test]
+fn mbc1_rom_banking() {
+    let mut cart = from_rom(banked_rom(0x01, 128, 0));
+    assert_eq!(cart.read(0x0000), 0);
+    assert_eq!(cart.read(0x4000), 1);
+    cart.write(0x2000, 0x00);
+    assert_eq!(cart.read(0x4000), 1, "Bank 0 should map to bank 1");
+    cart.write(0x2000, 0x1F);
+    assert_eq!(cart.read(0x4000), 0x1F);
+    cart.write(0x4000, 0x02);
+    assert_eq!(cart.read(0x4000), 0x5F);
+    assert_eq!(cart.read(0x0000), 0);
+    cart.write(0x6000, 0x01);
+    assert_eq!(cart.read(0x0000), 0x40);
+    // Bank numbers wrap to the ROM size.
+    let mut cart = from_rom(banked_rom(0x01, 4, 0));
+    cart.write(0x2000, 0x05);
+    assert_eq!(cart.read(0x4000), 1);
+}
+
+#[test]
+fn mbc1_ram_banking() {
+    let mut cart = from_rom(banked_rom(0x03, 4, 0x03));
+    cart.write(0xA000, 0x12);
+    assert_eq!(cart.read(0xA000), 0xFF, "RAM should be disabled on start");
+    cart.write(0x0000, 0x0A);
+    cart.write(0xA000, 0x12);
+    assert_eq!(cart.read(0xA000), 0x12);
+    cart.write(0x6000, 0x01);
+    cart.write(0x4000, 0x01);
+    assert_eq!(cart.read(0xA000), 0x00);
+    cart.write(0xA000, 0x34);
+    cart.write(0x4000, 0x00);
+    assert_eq!(cart.read(0xA000), 0x12);
+    cart.write(0x4000, 0x01);
+    assert_eq!(cart.read(0xA000), 0x34);
+    cart.write(0x0000, 0x00);
+    assert_eq!(cart.read(0xA000), 0xFF);
+}
+
+#[test]
+fn mbc2() {
+    let mut cart = from_rom(banked_rom(0x06, 16, 0));
+    // Address bit 8 set selects the ROM bank register.
+    cart.write(0x2100, 0x0F);
+    assert_eq!(cart.read(0x4000), 0x0F);
+    cart.write(0x0100, 0x00);
+    assert_eq!(cart.read(0x4000), 1);
+    // Address bit 8 clear is RAM enable, it shouldn't touch the bank.
+    cart.write(0x0000, 0x0A);
+    assert_eq!(cart.read(0x4000), 1);
+    cart.write(0xA000, 0xAB);
+    assert_eq!(cart.read(0xA000), 0xFB);
+    assert_eq!(cart.read(0xA200), 0xFB);
+    assert_eq!(cart.read(0xBE00), 0xFB);
+    cart.write(0x0000, 0x00);
+    assert_eq!(cart.read(0xA000), 0xFF);
+}
+
+#[test]
+fn mbc3_banking() {
+    let mut cart = from_rom(banked_rom(0x13, 128, 0x03));
+    cart.write(0x2000, 0x00);
+    assert_eq!(cart.read(0x4000), 1);
+    cart.write(0x2000, 0x7F);
+    assert_eq!(cart.read(0x4000), 0x7F);
+    cart.write(0x0000, 0x0A);
+    cart.write(0x4000, 0x02);
+    cart.write(0xA000, 0x56);
+    cart.write(0x4000, 0x00);
+    assert_eq!(cart.read(0xA000), 0x00);
+    cart.write(0x4000, 0x02);
+    assert_eq!(cart.read(0xA000), 0x56);
+    assert!(cart.rtc().is_none());
+}
+
+#[test]
+fn mbc3_rtc() {
+    let mut cart = from_rom(banked_rom(0x10, 4, 0x03));
+    cart.rtc().unwrap().clock = RtcClock::Cycles;
+    cart.write(0x0000, 0x0A);
+    // Set the clock to 23:59:59 on day 511.
+    cart.write(0x4000, 0x08);
+    cart.write(0xA000, 59);
+    cart.write(0x4000, 0x09);
+    cart.write(0xA000, 59);
+    cart.write(0x4000, 0x0A);
+    cart.write(0xA000, 23);
+    cart.write(0x4000, 0x0B);
+    cart.write(0xA000, 0xFF);
+    cart.write(0x4000, 0x0C);
+    cart.write(0xA000, 0x01);
+    for _ in 0..mbc3::RTC_CYCLES_PER_SECOND {
+        cart.tick();
+    }
+    // Registers are only visible after latching.
+    cart.write(0x4000, 0x08);
+    assert_eq!(cart.read(0xA000), 0);
+    cart.write(0x6000, 0x00);
+    cart.write(0x6000, 0x01);
+    let rtc = cart.rtc().unwrap();
+    assert_eq!(rtc.latched, [0, 0, 0, 0, 0b1000_0000]);
+
+    // Halted clocks don't advance.
+    cart.write(0x4000, 0x0C);
+    cart.write(0xA000, 0b0100_0000);
+    for _ in 0..mbc3::RTC_CYCLES_PER_SECOND {
+        cart.tick();
+    }
+    cart.write(0x6000, 0x00);
+    cart.write(0x6000, 0x01);
+    cart.write(0x4000, 0x08);
+    assert_eq!(cart.read(0xA000), 0);
+    cart.write(0x4000, 0x0C);
+    assert_eq!(cart.read(0xA000), 0b0100_0000);
+}
+
+#[test]
+fn mbc5_banking() {
+    let mut rom = banked_rom(0x1B, 512, 0x04);
+    rom[0x100 * ROM_BANK_SIZE + 1] = 0xAB;
+    let mut cart = from_rom(rom);
+    cart.write(0x2000, 0x00);
+    assert_eq!(cart.read(0x4000), 0, "Bank 0 is selectable on MBC5");
+    cart.write(0x2000, 0xFF);
+    assert_eq!(cart.read(0x4000), 0xFF);
+    cart.write(0x2000, 0x00);
+    cart.write(0x3000, 0x01);
+    assert_eq!(cart.read(0x4001), 0xAB);
+    cart.write(0x0000, 0x0A);
+    cart.write(0x4000, 0x0F);
+    cart.write(0xA000, 0x77);
+    cart.write(0x4000, 0x07);
+    assert_eq!(cart.read(0xA000), 0x00);
+    cart.write(0x4000, 0x0F);
+    assert_eq!(cart.read(0xA000), 0x77);
+    assert!(!cart.rumble());
+}
+
+#[test]
+fn mbc5_rumble() {
+    let mut cart = from_rom(banked_rom(0x1E, 4, 0x03));
+    cart.write(0x0000, 0x0A);
+    cart.write(0x4000, 0x09);
+    assert!(cart.rumble());
+    cart.write(0xA000, 0x42);
+    cart.write(0x4000, 0x01);
+    assert!(!cart.rumble());
+    assert_eq!(cart.read(0xA000), 0x42);
+}
+
+#[test]
+fn header() {
+    let rom = include_bytes!("../../test_roms/01-special.gb");
+    let header = Header::parse(rom).unwrap();
+    assert_eq!(header.title, "");
+    assert_eq!(header.cartridge_type, 0x01);
+    assert_eq!(header.rom_size, 0x8000);
+    assert_eq!(header.ram_size, 0);
+    assert_eq!(header.cgb, CgbSupport::Supported);
+    assert_eq!(header.verify_global_checksum(rom), Ok(()));
+}
+
+#[test]
+fn header_errors() {
+    let rom = include_bytes!("../../test_roms/01-special.gb");
+    assert_eq!(
+        Header::parse(&rom[..0x100]),
+        Err(HeaderError::Truncated {
+            expected: 0x150,
+            actual: 0x100
+        })
+    );
+    assert_eq!(
+        Header::parse(&rom[..0x4000]),
+        Err(HeaderError::Truncated {
+            expected: 0x8000,
+            actual: 0x4000
+        })
+    );
+
+    let mut bad = rom.to_vec();
+    bad[header::TITLE] = b'A';
+    assert!(matches!(
+        Header::parse(&bad),
+        Err(HeaderError::HeaderChecksum { .. })
+    ));
+    bad[header::HEADER_CHECKSUM] = header_checksum(&bad);
+    bad[0x7FFF] ^= 0xFF;
+    let header = Header::parse(&bad).unwrap();
+    assert_eq!(header.title, "A");
+    assert!(matches!(
+        header.verify_global_checksum(&bad),
+        Err(HeaderError::GlobalChecksum { .. })
+    ));
+}
+
+#[test]
+fn save_data() {
+    let mut cart = from_rom(banked_rom(0x10, 4, 0x02));
+    assert!(cart.has_battery());
+    cart.rtc().unwrap().clock = RtcClock::Cycles;
+    cart.write(0x0000, 0x0A);
+    cart.write(0xA123, 0x99);
+    cart.write(0x4000, 0x0A);
+    cart.write(0xA000, 12);
+    let data = cart.save_data();
+    assert_eq!(data.len(), RAM_BANK_SIZE + 48);
+    assert_eq!(data[0x123], 0x99);
+    assert_eq!(data[RAM_BANK_SIZE + 8], 12);
+
+    let mut restored = from_rom(banked_rom(0x10, 4, 0x02));
+    restored.rtc().unwrap().clock = RtcClock::Cycles;
+    restored.load_save_data(&data);
+    assert_eq!(restored.ram(), cart.ram());
+    assert_eq!(restored.rtc().unwrap().hours, 12);
+
+    // Files without the RTC footer still load the RAM.
+    let mut restored = from_rom(banked_rom(0x10, 4, 0x02));
+    restored.load_save_data(&data[..RAM_BANK_SIZE]);
+    assert_eq!(restored.ram(), cart.ram());
+    assert!(!from_rom(banked_rom(0x01, 4, 0x02)).has_battery());
+}
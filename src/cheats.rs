@@ -0,0 +1,307 @@
+use std::{error::Error, fmt::Display, fs, io, path::Path};
+
+use crate::bus::{Bus, Memory};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CheatKind {
+    // Replaces a ROM byte as it is read, optionally only if it matches `compare`.
+    GameGenie {
+        address: u16,
+        value: u8,
+        compare: Option<u8>,
+    },
+    // Writes a byte to RAM every frame.
+    GameShark { bank: u8, address: u16, value: u8 },
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cheat {
+    pub code: String,
+    pub name: String,
+    pub kind: CheatKind,
+    pub enabled: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum CheatError {
+    InvalidCode(String),
+    // Game Genie codes can only patch ROM.
+    NotRom(u16),
+    // GameShark codes can only write external and work RAM.
+    NotRam(u16),
+}
+
+impl Display for CheatError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CheatError::InvalidCode(code) => write!(
+                f,
+                "Invalid cheat code {:?}, expected ABC-DEF(-GHI) or ABCDEFGH",
+                code
+            ),
+            CheatError::NotRom(address) => {
+                write!(f, "Game Genie code patches {:04x}, outside of ROM", address)
+            }
+            CheatError::NotRam(address) => {
+                write!(f, "GameShark code writes {:04x}, outside of RAM", address)
+            }
+        }
+    }
+}
+
+impl Error for CheatError {}
+
+fn hex_digits(code: &str) -> Option<Vec<u8>> {
+    code.chars()
+        .map(|c| c.to_digit(16).map(|d| d as u8))
+        .collect()
+}
+
+// https://gbdev.gg8.se/wiki/articles/Gameshark_and_Game_Genie_codes
+impl CheatKind {
+    pub fn parse(code: &str) -> Result<CheatKind, CheatError> {
+        let invalid = || CheatError::InvalidCode(code.to_string());
+        let groups: Vec<&str> = code.split('-').collect();
+        match groups.as_slice() {
+            // Game Genie, ABC-DEF or ABC-DEF-GHI.
+            [a, b, rest @ ..] if a.len() == 3 && b.len() == 3 && rest.len() <= 1 => {
+                let d = hex_digits(&groups.concat()).ok_or_else(invalid)?;
+                if d.len() != 6 && d.len() != 9 {
+                    return Err(invalid());
+                }
+                let value = (d[0] << 4) | d[1];
+                let address = (((d[5] ^ 0xF) as u16) << 12)
+                    | ((d[2] as u16) << 8)
+                    | ((d[3] as u16) << 4)
+                    | d[4] as u16;
+                if address >= 0x8000 {
+                    return Err(CheatError::NotRom(address));
+                }
+                // H is a checksum we don't bother with.
+                let compare = if d.len() == 9 {
+                    Some(((d[6] << 4) | d[8]).rotate_right(2) ^ 0xBA)
+                } else {
+                    None
+                };
+                Ok(CheatKind::GameGenie {
+                    address,
+                    value,
+                    compare,
+                })
+            }
+            // GameShark, BBVVLLHH.
+            [code] if code.len() == 8 => {
+                let d = hex_digits(code).ok_or_else(invalid)?;
+                let byte = |i: usize| (d[i] << 4) | d[i + 1];
+                let address = u16::from_le_bytes([byte(4), byte(6)]);
+                if !(0xA000..=0xDFFF).contains(&address) {
+                    return Err(CheatError::NotRam(address));
+                }
+                Ok(CheatKind::GameShark {
+                    bank: byte(0),
+                    value: byte(2),
+                    address,
+                })
+            }
+            _ => Err(invalid()),
+        }
+    }
+}
+
+impl Cheat {
+    pub fn new(code: &str, name: &str) -> Result<Cheat, CheatError> {
+        let code = code.trim().to_uppercase();
+        Ok(Cheat {
+            kind: CheatKind::parse(&code)?,
+            code,
+            name: name.trim().to_string(),
+            enabled: true,
+        })
+    }
+}
+
+// Cheat list for one ROM. In the cheat file each line is a code followed by
+// an optional name, `#` starts a comment and a leading `!` disables the cheat.
+#[derive(Debug, Clone, Default)]
+pub struct Cheats {
+    pub list: Vec<Cheat>,
+}
+
+impl Cheats {
+    pub fn parse(text: &str) -> Result<Cheats, CheatError> {
+        let mut cheats = Cheats::default();
+        for line in text.lines().map(str::trim) {
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let (enabled, line) = match line.strip_prefix('!') {
+                Some(line) => (false, line.trim_start()),
+                None => (true, line),
+            };
+            let (code, name) = line.split_at(line.find(char::is_whitespace).unwrap_or(line.len()));
+            let mut cheat = Cheat::new(code, name)?;
+            cheat.enabled = enabled;
+            cheats.list.push(cheat);
+        }
+        Ok(cheats)
+    }
+
+    pub fn load(path: &Path) -> Result<Cheats, Box<dyn Error>> {
+        Ok(Cheats::parse(&fs::read_to_string(path)?)?)
+    }
+
+    pub fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, self.to_string())
+    }
+
+    pub fn add(&mut self, code: &str, name: &str) -> Result<(), CheatError> {
+        self.list.push(Cheat::new(code, name)?);
+        Ok(())
+    }
+
+    pub fn remove(&mut self, index: usize) -> Cheat {
+        self.list.remove(index)
+    }
+
+    pub fn toggle(&mut self, index: usize) {
+        if let Some(cheat) = self.list.get_mut(index) {
+            cheat.enabled = !cheat.enabled;
+        }
+    }
+
+    fn enabled(&self) -> impl Iterator<Item = &CheatKind> {
+        self.list.iter().filter(|c| c.enabled).map(|c| &c.kind)
+    }
+
+    // Applies Game Genie codes to `value`, read from ROM at `address`.
+    pub fn patch_rom(&self, address: u16, value: u8) -> u8 {
+        for kind in self.enabled() {
+            if let CheatKind::GameGenie {
+                address: a,
+                value: v,
+                compare,
+            } = *kind
+            {
+                if a == address && compare.is_none_or(|c| c == value) {
+                    return v;
+                }
+            }
+        }
+        value
+    }
+
+    // Applies GameShark codes, called once per frame at VBlank. The bank
+    // picks the external RAM bank, work RAM isn't banked on DMG.
+    pub fn write_ram(&self, bus: &mut Bus) {
+        for kind in self.enabled() {
+            if let CheatKind::GameShark {
+                bank,
+                address,
+                value,
+            } = *kind
+            {
+                match address {
+                    0xA000..=0xBFFF => bus.cartridge.poke_ram(bank as usize, address, value),
+                    _ => bus.write(address, value),
+                }
+            }
+        }
+    }
+}
+
+impl Display for Cheats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for cheat in &self.list {
+            let disabled = if cheat.enabled { "" } else { "!" };
+            writeln!(f, "{}{} {}", disabled, cheat.code, cheat.name)?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn game_genie() {
+        assert_eq!(
+            CheatKind::parse("00A-17B-C49"),
+            Ok(CheatKind::GameGenie {
+                address: 0x4A17,
+                value: 0x00,
+                compare: Some(0xC8),
+            })
+        );
+        assert_eq!(
+            CheatKind::parse("3E1-C2F"),
+            Ok(CheatKind::GameGenie {
+                address: 0x01C2,
+                value: 0x3E,
+                compare: None,
+            })
+        );
+        assert_eq!(
+            CheatKind::parse("001-234"),
+            Err(CheatError::NotRom(0xB123))
+        );
+        let cheats = Cheats::parse("00A-17B-C49 Infinite lives").unwrap();
+        assert_eq!(cheats.patch_rom(0x4A17, 0xC8), 0x00);
+        assert_eq!(cheats.patch_rom(0x4A17, 0x12), 0x12);
+        assert_eq!(cheats.patch_rom(0x4A18, 0xC8), 0xC8);
+    }
+
+    #[test]
+    fn game_shark() {
+        assert_eq!(
+            CheatKind::parse("010238CD"),
+            Ok(CheatKind::GameShark {
+                bank: 0x01,
+                value: 0x02,
+                address: 0xCD38,
+            })
+        );
+        assert!(CheatKind::parse("010238C").is_err());
+        assert!(CheatKind::parse("0102G8CD").is_err());
+        // Writing ROM would hit the mapper's registers instead.
+        assert_eq!(
+            CheatKind::parse("01010020"),
+            Err(CheatError::NotRam(0x2000))
+        );
+        assert_eq!(
+            CheatKind::parse("010180FF"),
+            Err(CheatError::NotRam(0xFF80))
+        );
+    }
+
+    #[test]
+    fn game_shark_banks() {
+        // MBC1 with 4 banks of RAM, bank 0 mapped in.
+        let mut rom = vec![0; 0x8000];
+        rom[0x147] = 0x03;
+        rom[0x149] = 0x03;
+        let mut bus = Bus::new(rom, None);
+        bus.write(0x0000, 0x0A);
+        let cheats = Cheats::parse("024200A0\n01240DC0").unwrap();
+        cheats.write_ram(&mut bus);
+        assert_eq!(bus.cartridge.ram()[2 * 0x2000], 0x42);
+        assert_eq!(bus.read(0xA000), 0x00);
+        assert_eq!(bus.read(0xC00D), 0x24);
+    }
+
+    #[test]
+    fn cheat_file() {
+        let text = "# Some game\n010238CD Max health\n!3E1-C2F\n";
+        let mut cheats = Cheats::parse(text).unwrap();
+        assert_eq!(cheats.list.len(), 2);
+        assert_eq!(cheats.list[0].name, "Max health");
+        assert!(!cheats.list[1].enabled);
+        assert_eq!(cheats.patch_rom(0x01C2, 0x00), 0x00);
+        cheats.toggle(1);
+        assert_eq!(cheats.patch_rom(0x01C2, 0x00), 0x3E);
+        assert_eq!(
+            Cheats::parse(&cheats.to_string()).unwrap().list,
+            cheats.list
+        );
+    }
+}
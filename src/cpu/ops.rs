@@ -1,89 +1,124 @@
-use crate::bus::Bus;
-use crate::cpu::value::Value::*;
-use crate::cpu::value::Writable;
+use crate::bus::{Bus, Device};
 use crate::cpu::CPU;
+use crate::cpu::CPUState;
 use crate::instructions::Flag;
-use crate::instructions::Location;
-use crate::instructions::Register;
-use crate::instructions::Register::*;
+use crate::instructions::{Executable, CB_TABLE};
+use crate::instructions::Register16::*;
+use crate::instructions::{Location, Location16, Register16};
+use crate::timer::DIV;
+
+// Every op returns None when it has used up the current M-cycle, and is run
+// again from the top on the next one, see `CPU::bus_cycle`. So registers and
+// flags may only change after the op's last bus access.
 impl CPU {
-    pub fn noop(&mut self, _bus: &mut Bus) {}
+    pub fn noop(&mut self, _bus: &mut Bus) -> Option<()> {
+        Some(())
+    }
 
-    pub fn ld(&mut self, into: Location, from: Location, bus: &mut Bus) {
-        let from_value = self.read_from(from, bus);
+    pub fn ld(&mut self, into: Location, from: Location, bus: &mut Bus) -> Option<()> {
+        let from_value = self.read_from(from, bus)?;
         self.write_into(into, from_value, bus)
     }
 
-    pub fn inc_mem(&mut self, r: Register, bus: &mut Bus) {
-        let address = self.registers.fetch_u16(r);
-        let value = bus.read_cycle(address);
+    pub fn ld16(&mut self, into: Location16, from: Location16, bus: &mut Bus) -> Option<()> {
+        let value = self.read_u16(from, bus)?;
+        self.write_u16(into, value, bus)
+    }
+
+    pub fn inc(&mut self, location: Location, bus: &mut Bus) -> Option<()> {
+        let value = self.read_from(location, bus)?;
         let result = value.wrapping_add(1);
-        bus.write_cycle(address, result);
+        self.write_into(location, result, bus)?;
         self.registers.set_zf(result == 0);
         self.registers.set_nf(false);
         self.registers.set_hf(value & 0x0f == 0x0f);
+        Some(())
     }
-    pub fn inc_reg(&mut self, r: Register, bus: &mut Bus) {
-        self.registers.inc(r);
-        if r.is_dual_register() {
-            bus.generic_cycle();
-        }
-    }
-    pub fn dec_mem(&mut self, r: Register, bus: &mut Bus) {
-        let address = self.registers.fetch_u16(r);
-        let value = bus.read_cycle(address);
+    pub fn dec(&mut self, location: Location, bus: &mut Bus) -> Option<()> {
+        let value = self.read_from(location, bus)?;
         let result = value.wrapping_sub(1);
-        bus.write_cycle(address, result);
+        self.write_into(location, result, bus)?;
         self.registers.set_zf(result == 0);
         self.registers.set_nf(true);
         self.registers.set_hf(result & 0x0f == 0x0f);
+        Some(())
     }
-    pub fn dec_reg(&mut self, r: Register, bus: &mut Bus) {
+    pub fn inc16(&mut self, r: Register16, bus: &mut Bus) -> Option<()> {
+        self.internal_cycle(bus)?;
+        self.registers.inc(r);
+        Some(())
+    }
+    pub fn dec16(&mut self, r: Register16, bus: &mut Bus) -> Option<()> {
+        self.internal_cycle(bus)?;
         self.registers.dec(r);
-        if r.is_dual_register() {
-            bus.generic_cycle();
-        }
+        Some(())
     }
 
-    pub fn ldi(&mut self, into: Location, from: Location, bus: &mut Bus) {
-        self.ld(into, from, bus);
-        self.registers.inc(Register::HL);
-    }
-    pub fn ldd(&mut self, into: Location, from: Location, bus: &mut Bus) {
-        self.ld(into, from, bus);
-        self.registers.dec(Register::HL);
-    }
-    pub fn rst(&mut self, size: u16, bus: &mut Bus) {
-        bus.generic_cycle();
-        self.push_stack(self.registers.pc, bus);
+    pub fn ldi(&mut self, into: Location, from: Location, bus: &mut Bus) -> Option<()> {
+        self.ld(into, from, bus)?;
+        self.registers.inc(HL);
+        Some(())
+    }
+    pub fn ldd(&mut self, into: Location, from: Location, bus: &mut Bus) -> Option<()> {
+        self.ld(into, from, bus)?;
+        self.registers.dec(HL);
+        Some(())
+    }
+    pub fn rst(&mut self, size: u16, bus: &mut Bus) -> Option<()> {
+        self.internal_cycle(bus)?;
+        self.push_stack(self.registers.pc, bus)?;
         self.registers.pc = size;
+        Some(())
     }
-    pub fn ldsp(&mut self, bus: &mut Bus) {
-        let offset = self.next_u8(bus) as i8 as u16;
+    pub fn ldsp(&mut self, bus: &mut Bus) -> Option<()> {
+        let offset = self.next_u8(bus)? as i8 as u16;
+        self.internal_cycle(bus)?;
         let result = self.registers.sp.wrapping_add(offset); // todo ?
         let half_carry = (self.registers.sp & 0x0F).wrapping_add(offset & 0x0F) > 0x0F;
         let carry = (self.registers.sp & 0xFF).wrapping_add(offset & 0xFF) > 0xFF;
-        self.write_into(Location::Register(HL), U16(result), bus);
-        bus.generic_cycle();
+        self.registers.set_u16(HL, result);
         self.registers.set_zf(false);
         self.registers.set_nf(false);
         self.registers.set_hf(half_carry);
         self.registers.set_cf(carry);
+        Some(())
+    }
+    // STOP is followed by a padding byte, which is only skipped in some cases.
+    // https://gbdev.io/pandocs/Reducing_Power_Consumption.html#using-the-stop-instruction
+    pub fn stop(&mut self, bus: &mut Bus) -> Option<()> {
+        let pending = bus.int_enabled & bus.int_flags != 0;
+        if bus.joypad_lines() != 0x0F {
+            // A held button keeps it from stopping, at most it halts.
+            if !pending {
+                self.registers.pc = self.registers.pc.wrapping_add(1);
+                self.state = CPUState::Halted;
+            }
+            return Some(());
+        }
+        if !pending {
+            self.registers.pc = self.registers.pc.wrapping_add(1);
+        }
+        bus.timer.write(DIV as u16, 0, &mut bus.int_flags);
+        if bus.speed_switch_armed {
+            // The switch itself takes a while, we just carry on at the new speed.
+            bus.switch_speed();
+            return Some(());
+        }
+        self.state = CPUState::Stopped;
+        Some(())
     }
-    pub fn stop(&mut self, _bus: &mut Bus) {
-        // println!("stop: {:04x}", self.registers.pc - 1); // todo ?
-    }
-    pub fn cp(&mut self, location: Location, bus: &mut Bus) {
-        let value = self.read_from(location, bus).into();
+    pub fn cp(&mut self, location: Location, bus: &mut Bus) -> Option<()> {
+        let value = self.read_from(location, bus)?;
         self.registers.set_zf(self.registers.a == value);
         self.registers.set_nf(true);
         //https://github.com/gekkio/mooneye-gb/blob/ca7ff30b52fd3de4f1527397f27a729ffd848dfa/core/src/self.rs#l156
         self.registers
             .set_hf((self.registers.a & 0xf).wrapping_sub(value & 0xf) & (0xf + 1) != 0);
         self.registers.set_cf(self.registers.a < value);
+        Some(())
     }
-    pub fn add(&mut self, location: Location, bus: &mut Bus) {
-        let value = self.read_from(location, bus).into();
+    pub fn add(&mut self, location: Location, bus: &mut Bus) -> Option<()> {
+        let value = self.read_from(location, bus)?;
         let (result, carry) = self.registers.a.overflowing_add(value);
         //https://github.com/gekkio/mooneye-gb/blob/ca7ff30b52fd3de4f1527397f27a729ffd848dfa/core/src/self/execute.rs#l55
         let half_carry = (self.registers.a & 0x0f)
@@ -94,9 +129,10 @@ impl CPU {
         self.registers.set_nf(false);
         self.registers.set_hf(half_carry);
         self.registers.set_cf(carry);
+        Some(())
     }
-    pub fn sub(&mut self, location: Location, bus: &mut Bus) {
-        let value = self.read_from(location, bus).into();
+    pub fn sub(&mut self, location: Location, bus: &mut Bus) -> Option<()> {
+        let value = self.read_from(location, bus)?;
         let result = self.registers.a.wrapping_sub(value);
         self.registers.set_zf(result == 0);
         self.registers.set_nf(true);
@@ -107,9 +143,10 @@ impl CPU {
         self.registers
             .set_cf((self.registers.a as u16) < (value as u16));
         self.registers.a = result;
+        Some(())
     }
-    pub fn adc(&mut self, location: Location, bus: &mut Bus) {
-        let value = self.read_from(location, bus).into();
+    pub fn adc(&mut self, location: Location, bus: &mut Bus) -> Option<()> {
+        let value = self.read_from(location, bus)?;
         let carry = self.registers.flg_c() as u8;
         let result = self.registers.a.wrapping_add(value).wrapping_add(carry);
         self.registers.set_zf(result == 0);
@@ -120,126 +157,144 @@ impl CPU {
         self.registers
             .set_cf(self.registers.a as u16 + value as u16 + carry as u16 > 0xff);
         self.registers.a = result;
+        Some(())
     }
-    pub fn addhl(&mut self, location: Location, bus: &mut Bus) {
+    pub fn addhl(&mut self, r: Register16, bus: &mut Bus) -> Option<()> {
+        self.internal_cycle(bus)?;
         let hl = self.registers.hl();
-        if let U16(value) = self.read_from(location, bus) {
-            if location.is_dual_register() {
-                bus.generic_cycle();
-            }
-            let (result, overflow) = hl.overflowing_add(value);
-            let [h, l] = result.to_be_bytes();
-            self.registers.h = h;
-            self.registers.l = l;
-            self.registers.set_nf(false);
-            self.registers
-                .set_hf((hl & 0xfff) + (value & 0xfff) > 0x0fff);
-            self.registers.set_cf(overflow);
-        } else {
-            unimplemented!()
-        }
+        let value = self.registers.fetch_u16(r);
+        let (result, overflow) = hl.overflowing_add(value);
+        self.registers.set_u16(HL, result);
+        self.registers.set_nf(false);
+        self.registers
+            .set_hf((hl & 0xfff) + (value & 0xfff) > 0x0fff);
+        self.registers.set_cf(overflow);
+        Some(())
     }
-    pub fn and(&mut self, location: Location, bus: &mut Bus) {
-        let value: u8 = self.read_from(location, bus).into();
+    pub fn and(&mut self, location: Location, bus: &mut Bus) -> Option<()> {
+        let value = self.read_from(location, bus)?;
         self.registers.a &= value;
         self.registers.set_zf(self.registers.a == 0);
         self.registers.set_nf(false);
         self.registers.set_hf(true);
         self.registers.set_cf(false);
+        Some(())
     }
-    pub fn xor(&mut self, location: Location, bus: &mut Bus) {
-        let value: u8 = self.read_from(location, bus).into();
+    pub fn xor(&mut self, location: Location, bus: &mut Bus) -> Option<()> {
+        let value = self.read_from(location, bus)?;
         self.registers.a ^= value;
         self.registers.set_zf(self.registers.a == 0);
         self.registers.set_nf(false);
         self.registers.set_hf(false);
         self.registers.set_cf(false);
+        Some(())
     }
-    pub fn orr(&mut self, location: Location, bus: &mut Bus) {
-        let value: u8 = self.read_from(location, bus).into();
+    pub fn orr(&mut self, location: Location, bus: &mut Bus) -> Option<()> {
+        let value = self.read_from(location, bus)?;
         self.registers.a |= value;
         self.registers.set_zf(self.registers.a == 0);
         self.registers.set_nf(false);
         self.registers.set_hf(false);
         self.registers.set_cf(false);
+        Some(())
     }
-    pub fn not(&mut self, location: Location, bus: &mut Bus) {
-        let value: u8 = self.read_from(location, bus).into();
+    pub fn not(&mut self, location: Location, bus: &mut Bus) -> Option<()> {
+        let value = self.read_from(location, bus)?;
         self.registers.a = !value;
         self.registers.set_nf(true);
         self.registers.set_hf(true);
+        Some(())
     }
-    pub fn ccf(&mut self, _bus: &mut Bus) {
+    pub fn ccf(&mut self, _bus: &mut Bus) -> Option<()> {
         self.registers.set_nf(false);
         self.registers.set_hf(false);
         self.registers.set_cf(!self.registers.flg_c());
+        Some(())
     }
-    pub fn scf(&mut self, _bus: &mut Bus) {
+    pub fn scf(&mut self, _bus: &mut Bus) -> Option<()> {
         self.registers.set_nf(false);
         self.registers.set_hf(false);
         self.registers.set_cf(true);
-    }
-    pub fn halt(&mut self, _bus: &mut Bus) {
-        //todo
-        self.halt = true;
-    }
-    pub fn jumping<F: FnOnce(&mut Self, &mut Bus)>(
-        &mut self,
-        jt: Option<Flag>,
-        bus: &mut Bus,
-        f: F,
-    ) {
-        if let Some(false) = jt.map(|flag| self.check_flag(flag)) {
-            return;
+        Some(())
+    }
+    // 0xD3, 0xDB, 0xDD, 0xE3, 0xE4, 0xEB-0xED, 0xF4, 0xFC and 0xFD hang the CPU.
+    pub fn lock(&mut self, _bus: &mut Bus) -> Option<()> {
+        self.state = CPUState::Locked;
+        Some(())
+    }
+    // https://gbdev.io/pandocs/halt.html
+    pub fn halt(&mut self, bus: &mut Bus) -> Option<()> {
+        if bus.int_enabled & bus.int_flags == 0 {
+            self.state = CPUState::Halted;
+        } else if bus.ime == 0 {
+            self.halt_bug = true;
         }
-        f(self, bus);
-        bus.generic_cycle();
+        // With IME set the pending interrupt is dispatched right away.
+        Some(())
     }
-    pub fn jp(&mut self, jump_type: Option<Flag>, bus: &mut Bus) {
-        let address = self.next_u16(bus);
-        self.jumping(jump_type, bus, |cpu, _| cpu.registers.pc = address);
+    fn condition(&mut self, jt: Option<Flag>) -> bool {
+        jt.is_none_or(|flag| self.check_flag(flag))
     }
-    pub fn jp_hl(&mut self, _bus: &mut Bus) {
+    pub fn jp(&mut self, jump_type: Option<Flag>, bus: &mut Bus) -> Option<()> {
+        let address = self.next_u16(bus)?;
+        if self.condition(jump_type) {
+            self.internal_cycle(bus)?;
+            self.registers.pc = address;
+        }
+        Some(())
+    }
+    pub fn jp_hl(&mut self, _bus: &mut Bus) -> Option<()> {
         self.registers.pc = self.registers.hl();
+        Some(())
     }
-    pub fn jr(&mut self, jump_type: Option<Flag>, bus: &mut Bus) {
-        let offset = self.next_u8(bus) as i8;
-        let address = self.registers.pc.wrapping_add(offset as u16);
-        self.jumping(jump_type, bus, |cpu, _| {
-            cpu.registers.pc = address;
-        });
-    }
-    pub fn call(&mut self, jump_type: Option<Flag>, bus: &mut Bus) {
-        let address = self.next_u16(bus);
-        self.jumping(jump_type, bus, |cpu, bus| {
-            cpu.push_stack(cpu.registers.pc, bus);
-            cpu.registers.pc = address;
-        });
-    }
-    pub fn push(&mut self, register: Register, bus: &mut Bus) {
+    pub fn jr(&mut self, jump_type: Option<Flag>, bus: &mut Bus) -> Option<()> {
+        let offset = self.next_u8(bus)? as i8;
+        if self.condition(jump_type) {
+            self.internal_cycle(bus)?;
+            self.registers.pc = self.registers.pc.wrapping_add(offset as u16);
+        }
+        Some(())
+    }
+    pub fn call(&mut self, jump_type: Option<Flag>, bus: &mut Bus) -> Option<()> {
+        let address = self.next_u16(bus)?;
+        if self.condition(jump_type) {
+            self.internal_cycle(bus)?;
+            self.push_stack(self.registers.pc, bus)?;
+            self.registers.pc = address;
+        }
+        Some(())
+    }
+    pub fn push(&mut self, register: Register16, bus: &mut Bus) -> Option<()> {
+        self.internal_cycle(bus)?;
         let value = self.registers.fetch_u16(register);
-        self.push_stack(value, bus);
-        bus.generic_cycle();
+        self.push_stack(value, bus)
     }
-    pub fn pop(&mut self, register: Register, bus: &mut Bus) {
-        let addr = self.pop_stack(bus);
-        addr.to_register(&mut self.registers, register);
+    pub fn pop(&mut self, register: Register16, bus: &mut Bus) -> Option<()> {
+        let addr = self.pop_stack(bus)?;
+        self.registers.set_u16(register, addr);
+        Some(())
     }
-    pub fn ret(&mut self, jump_type: Option<Flag>, bus: &mut Bus) {
-        self.jumping(jump_type, bus, |cpu, bus| {
-            cpu.registers.pc = cpu.pop_stack(bus);
-        });
+    pub fn ret(&mut self, jump_type: Option<Flag>, bus: &mut Bus) -> Option<()> {
+        // Checking the condition takes a cycle of its own.
         if jump_type.is_some() {
-            bus.generic_cycle();
+            self.internal_cycle(bus)?;
+        }
+        if self.condition(jump_type) {
+            let address = self.pop_stack(bus)?;
+            self.internal_cycle(bus)?;
+            self.registers.pc = address;
         }
+        Some(())
     }
-    pub fn enableinterrupts(&mut self, bus: &mut Bus) {
-        bus.enable_interrupts();
+    pub fn enableinterrupts(&mut self, _bus: &mut Bus) -> Option<()> {
+        self.ei_delay = true;
+        Some(())
     }
-    pub fn disableinterrupts(&mut self, bus: &mut Bus) {
+    pub fn disableinterrupts(&mut self, bus: &mut Bus) -> Option<()> {
         bus.disable_interrupts();
+        Some(())
     }
-    pub fn rra(&mut self, _bus: &mut Bus) {
+    pub fn rra(&mut self, _bus: &mut Bus) -> Option<()> {
         let carry = self.registers.a & 1 != 0;
         self.registers.a >>= 1;
         if self.registers.flg_c() {
@@ -249,8 +304,9 @@ impl CPU {
         self.registers.set_hf(false);
         self.registers.set_nf(false);
         self.registers.set_cf(carry);
+        Some(())
     }
-    pub fn rrca(&mut self, _bus: &mut Bus) {
+    pub fn rrca(&mut self, _bus: &mut Bus) -> Option<()> {
         let carry = self.registers.a & 1 != 0;
         self.registers.a >>= 1;
         if carry {
@@ -260,8 +316,9 @@ impl CPU {
         self.registers.set_hf(false);
         self.registers.set_nf(false);
         self.registers.set_cf(carry);
+        Some(())
     }
-    pub fn rla(&mut self, _bus: &mut Bus) {
+    pub fn rla(&mut self, _bus: &mut Bus) -> Option<()> {
         let overflow = self.registers.a & 0x80 != 0;
         let result = self.registers.a << 1;
         self.registers.a = result | (self.registers.flg_c() as u8);
@@ -269,8 +326,9 @@ impl CPU {
         self.registers.set_hf(false);
         self.registers.set_nf(false);
         self.registers.set_cf(overflow);
+        Some(())
     }
-    pub fn rlca(&mut self, _bus: &mut Bus) {
+    pub fn rlca(&mut self, _bus: &mut Bus) -> Option<()> {
         let carry = self.registers.a & 0x80 != 0;
         let result = self.registers.a << 1 | carry as u8;
         self.registers.a = result;
@@ -278,13 +336,14 @@ impl CPU {
         self.registers.set_hf(false);
         self.registers.set_nf(false);
         self.registers.set_cf(carry);
+        Some(())
     }
-    pub fn addsp(&mut self, bus: &mut Bus) {
-        let offset = self.next_u8(bus) as i8 as i16 as u16;
+    pub fn addsp(&mut self, bus: &mut Bus) -> Option<()> {
+        let offset = self.next_u8(bus)? as i8 as i16 as u16;
+        self.internal_cycle(bus)?;
+        self.internal_cycle(bus)?;
         let sp = self.registers.sp;
         let result = self.registers.sp.wrapping_add(offset);
-        bus.generic_cycle();
-        bus.generic_cycle();
         let half_carry = ((sp & 0x0f) + (offset & 0x0f)) > 0x0f;
         let overflow = ((sp & 0xff) + (offset & 0xff)) > 0xff;
         self.registers.sp = result;
@@ -292,19 +351,23 @@ impl CPU {
         self.registers.set_nf(false);
         self.registers.set_hf(half_carry);
         self.registers.set_cf(overflow);
+        Some(())
     }
-    pub fn reti(&mut self, bus: &mut Bus) {
+    // Unlike EI, RETI enables interrupts straight away.
+    pub fn reti(&mut self, bus: &mut Bus) -> Option<()> {
+        let addr = self.pop_stack(bus)?;
+        self.internal_cycle(bus)?;
         bus.enable_interrupts();
-        let addr = self.pop_stack(bus);
         self.registers.pc = addr;
-        bus.generic_cycle();
+        Some(())
     }
-    pub fn daa(&mut self, _bus: &mut Bus) {
+    pub fn daa(&mut self, _bus: &mut Bus) -> Option<()> {
         self.registers.a = self.bcd_adjust(self.registers.a);
+        Some(())
     }
-    pub fn sbc(&mut self, l: Location, bus: &mut Bus) {
+    pub fn sbc(&mut self, l: Location, bus: &mut Bus) -> Option<()> {
         let a = self.registers.a;
-        let value: u8 = self.read_from(l, bus).into();
+        let value = self.read_from(l, bus)?;
         let cy = self.registers.flg_c() as u8;
         let result = a.wrapping_sub(value).wrapping_sub(cy);
         self.registers.set_zf(result == 0);
@@ -320,122 +383,107 @@ impl CPU {
         self.registers
             .set_cf((self.registers.a as u16) < (value as u16) + (cy as u16));
         self.registers.a = result;
+        Some(())
     }
 
-    pub fn handle_cb(&mut self, bus: &mut Bus) {
-        let opcode = self.next_u8(bus);
-        let target = CPU::cb_location(opcode);
-        if let U8(value) = self.read_from(target, bus) {
-            match opcode {
-                0x00..=0x07 => {
-                    //RLC
-                    let carry = value & 0x80 != 0;
-                    let result = value << 1 | carry as u8;
-                    self.registers.set_zf(result == 0);
-                    self.registers.set_hf(false);
-                    self.registers.set_nf(false);
-                    self.registers.set_cf(carry);
-                    self.write_into(target, result, bus);
-                }
-                0x08..=0x0F => {
-                    //RRC
-                    let carry = value & 0x01 != 0;
-                    let result = ((carry as u8) << 7) | (value >> 1);
-                    self.registers.set_zf(result == 0);
-                    self.registers.set_hf(false);
-                    self.registers.set_nf(false);
-                    self.registers.set_cf(carry);
-                    self.write_into(target, result, bus);
-                }
-                0x10..=0x17 => {
-                    //RL
-                    let result = value << 1 | self.registers.flg_c() as u8;
-                    self.registers.set_zf(result == 0);
-                    self.registers.set_nf(false);
-                    self.registers.set_hf(false);
-                    self.registers.set_cf(value & 0x80 != 0);
-                    self.write_into(target, result, bus);
-                }
-                0x18..=0x1F => {
-                    //RR
-                    let result = (value >> 1) | ((self.registers.flg_c() as u8) << 7);
-                    self.registers.set_zf(result == 0);
-                    self.registers.set_nf(false);
-                    self.registers.set_hf(false);
-                    self.registers.set_cf(value & 0x01 != 0);
-                    self.write_into(target, result, bus);
-                }
-                0x30..=0x37 => {
-                    // SWAP
-                    let result = swapped_nibbles(value);
-                    self.registers.set_zf(result == 0);
-                    self.registers.set_nf(false);
-                    self.registers.set_hf(false);
-                    self.registers.set_cf(false);
-                    self.write_into(target, result, bus);
-                }
-                0x40..=0x7F => {
-                    // BIT
-                    let mut bit_index = (((opcode & 0xF0) >> 4) - 4) * 2;
-                    if opcode & 0x08 != 0 {
-                        bit_index += 1;
-                    }
-                    let check_zero = value & (1 << bit_index) == 0;
-                    self.registers.set_zf(check_zero);
-                    self.registers.set_nf(false);
-                    self.registers.set_hf(true);
-                    if let Location::Memory(_) = target {
-                        bus.generic_cycle();
-                    }
-                }
-                0xC0..=0xFF => {
-                    // SET
-                    let mut bit_index = (((opcode & 0xF0) >> 4) - 0xC) * 2;
-                    if opcode & 0x08 != 0 {
-                        bit_index += 1;
-                    }
-                    let result = value | (1 << bit_index);
-                    self.write_into(target, result, bus);
-                }
-                0x38..=0x3F => {
-                    let result = value >> 1;
-                    self.registers.set_zf(result == 0);
-                    self.registers.set_nf(false);
-                    self.registers.set_hf(false);
-                    self.registers.set_cf(value & 1 != 0);
-                    self.write_into(target, result, bus);
-                }
-                0x20..=0x27 => {
-                    // SLA
-                    let result = value << 1;
-                    self.registers.set_zf(result == 0);
-                    self.registers.set_nf(false);
-                    self.registers.set_hf(false);
-                    self.registers.set_cf(value & 0x80 != 0);
-                    self.write_into(target, result, bus);
-                }
-                0x28..=0x2F => {
-                    // SRA
-                    let result = value >> 1 | (value & 0x80);
-                    self.registers.set_zf(result == 0);
-                    self.registers.set_nf(false);
-                    self.registers.set_hf(false);
-                    self.registers.set_cf(value & 0x1 != 0);
-                    self.write_into(target, result, bus);
-                }
-                0x80..=0xBF => {
-                    // RES
-                    let mut bit_index = (((opcode & 0xF0) >> 4) - 8) * 2;
-                    if opcode & 0x08 != 0 {
-                        bit_index += 1;
-                    }
-                    let result = value & !(1 << bit_index);
-                    self.write_into(target, result, bus);
-                }
-            };
-        } else {
-            unreachable!();
+    pub fn handle_cb(&mut self, bus: &mut Bus) -> Option<()> {
+        let opcode = self.next_u8(bus)?;
+        CB_TABLE[opcode as usize].execute(self, bus)
+    }
+
+    // Flags shared by the CB rotates and shifts.
+    fn shift_flags(&mut self, result: u8, carry: bool) {
+        self.registers.set_zf(result == 0);
+        self.registers.set_nf(false);
+        self.registers.set_hf(false);
+        self.registers.set_cf(carry);
+    }
+
+    pub fn rlc(&mut self, target: Location, bus: &mut Bus) -> Option<()> {
+        let value = self.read_from(target, bus)?;
+        let carry = value & 0x80 != 0;
+        let result = value << 1 | carry as u8;
+        self.write_into(target, result, bus)?;
+        self.shift_flags(result, carry);
+        Some(())
+    }
+
+    pub fn rrc(&mut self, target: Location, bus: &mut Bus) -> Option<()> {
+        let value = self.read_from(target, bus)?;
+        let carry = value & 0x01 != 0;
+        let result = ((carry as u8) << 7) | (value >> 1);
+        self.write_into(target, result, bus)?;
+        self.shift_flags(result, carry);
+        Some(())
+    }
+
+    pub fn rl(&mut self, target: Location, bus: &mut Bus) -> Option<()> {
+        let value = self.read_from(target, bus)?;
+        let result = value << 1 | self.registers.flg_c() as u8;
+        self.write_into(target, result, bus)?;
+        self.shift_flags(result, value & 0x80 != 0);
+        Some(())
+    }
+
+    pub fn rr(&mut self, target: Location, bus: &mut Bus) -> Option<()> {
+        let value = self.read_from(target, bus)?;
+        let result = (value >> 1) | ((self.registers.flg_c() as u8) << 7);
+        self.write_into(target, result, bus)?;
+        self.shift_flags(result, value & 0x01 != 0);
+        Some(())
+    }
+
+    pub fn sla(&mut self, target: Location, bus: &mut Bus) -> Option<()> {
+        let value = self.read_from(target, bus)?;
+        let result = value << 1;
+        self.write_into(target, result, bus)?;
+        self.shift_flags(result, value & 0x80 != 0);
+        Some(())
+    }
+
+    pub fn sra(&mut self, target: Location, bus: &mut Bus) -> Option<()> {
+        let value = self.read_from(target, bus)?;
+        let result = value >> 1 | (value & 0x80);
+        self.write_into(target, result, bus)?;
+        self.shift_flags(result, value & 0x01 != 0);
+        Some(())
+    }
+
+    pub fn swap(&mut self, target: Location, bus: &mut Bus) -> Option<()> {
+        let value = self.read_from(target, bus)?;
+        let result = swapped_nibbles(value);
+        self.write_into(target, result, bus)?;
+        self.shift_flags(result, false);
+        Some(())
+    }
+
+    pub fn srl(&mut self, target: Location, bus: &mut Bus) -> Option<()> {
+        let value = self.read_from(target, bus)?;
+        let result = value >> 1;
+        self.write_into(target, result, bus)?;
+        self.shift_flags(result, value & 0x01 != 0);
+        Some(())
+    }
+
+    pub fn bit(&mut self, bit: u8, target: Location, bus: &mut Bus) -> Option<()> {
+        let value = self.read_from(target, bus)?;
+        if let Location::Memory(_) = target {
+            self.internal_cycle(bus)?;
         }
+        self.registers.set_zf(value & (1 << bit) == 0);
+        self.registers.set_nf(false);
+        self.registers.set_hf(true);
+        Some(())
+    }
+
+    pub fn res(&mut self, bit: u8, target: Location, bus: &mut Bus) -> Option<()> {
+        let value = self.read_from(target, bus)?;
+        self.write_into(target, value & !(1 << bit), bus)
+    }
+
+    pub fn set(&mut self, bit: u8, target: Location, bus: &mut Bus) -> Option<()> {
+        let value = self.read_from(target, bus)?;
+        self.write_into(target, value | (1 << bit), bus)
     }
 }
 
@@ -0,0 +1,246 @@
+// Runs the SM83 single step tests, https://github.com/SingleStepTests/sm83
+// They aren't checked in, so the test is ignored: point SM83_TESTS at the
+// directory holding the `xx.json` files (defaults to test_roms/sm83) and run it
+// with `cargo test sm83 -- --ignored`.
+//
+// A test starts with its opcode already fetched and PC past it, and ends with
+// the fetch of the next opcode, which is exactly what `CPU::step` runs.
+use super::*;
+use crate::bus::Device;
+use serde_json::Value as Json;
+use std::{cell::RefCell, fs, path::PathBuf, rc::Rc};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Access {
+    Read(u16, u8),
+    Write(u16, u8),
+}
+
+// All 64K as plain RAM, noting the M-cycle of every access.
+struct FlatRam {
+    data: Vec<u8>,
+    cycle: usize,
+    log: Rc<RefCell<Vec<(usize, Access)>>>,
+}
+
+impl Device for FlatRam {
+    fn read(&self, address: u16) -> u8 {
+        let value = self.data[address as usize];
+        self.log
+            .borrow_mut()
+            .push((self.cycle, Access::Read(address, value)));
+        value
+    }
+    fn write(&mut self, address: u16, value: u8, _int_flags: &mut u8) {
+        self.data[address as usize] = value;
+        self.log
+            .borrow_mut()
+            .push((self.cycle, Access::Write(address, value)));
+    }
+    fn tick(&mut self, _int_flags: &mut u8) {
+        self.cycle += 1;
+    }
+}
+
+fn field(state: &Json, name: &str) -> u16 {
+    state[name].as_u64().unwrap_or(0) as u16
+}
+
+fn ram(state: &Json) -> Vec<(u16, u8)> {
+    state["ram"]
+        .as_array()
+        .map(|ram| {
+            ram.iter()
+                .map(|entry| {
+                    (
+                        entry[0].as_u64().unwrap() as u16,
+                        entry[1].as_u64().unwrap() as u8,
+                    )
+                })
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+// Idle cycles are either null or have no read or write pin set.
+fn expected_access(cycle: &Json) -> Option<Access> {
+    let pins = cycle[2].as_str()?;
+    let address = cycle[0].as_u64()? as u16;
+    let value = cycle[1].as_u64().unwrap_or(0) as u8;
+    match pins.as_bytes() {
+        [b'r', ..] => Some(Access::Read(address, value)),
+        [_, b'w', ..] => Some(Access::Write(address, value)),
+        _ => None,
+    }
+}
+
+// Runs a single test, returning what differed.
+fn run(test: &Json) -> Result<(), String> {
+    let (initial, expected) = (&test["initial"], &test["final"]);
+    let log = Rc::new(RefCell::new(vec![]));
+    let mut memory = FlatRam {
+        data: vec![0; 0x10000],
+        cycle: 0,
+        log: log.clone(),
+    };
+    for (address, value) in ram(initial) {
+        memory.data[address as usize] = value;
+    }
+
+    let mut cpu = CPU::new();
+    let r = &mut cpu.registers;
+    r.a = field(initial, "a") as u8;
+    r.f = field(initial, "f") as u8;
+    r.b = field(initial, "b") as u8;
+    r.c = field(initial, "c") as u8;
+    r.d = field(initial, "d") as u8;
+    r.e = field(initial, "e") as u8;
+    r.h = field(initial, "h") as u8;
+    r.l = field(initial, "l") as u8;
+    r.sp = field(initial, "sp");
+    r.pc = field(initial, "pc");
+    cpu.op_addr = r.pc.wrapping_sub(1);
+    cpu.opcode = memory.data[cpu.op_addr as usize];
+
+    let mut bus = Bus::new(vec![], None);
+    bus.rom_start_signal = false;
+    bus.in_bios = 1;
+    bus.ime = field(initial, "ime") as u8;
+    bus.int_enabled = field(initial, "ie") as u8;
+    bus.map_device(0x0000..=0xFFFF, Box::new(memory));
+
+    let start = bus.clock;
+    cpu.step(&mut bus);
+    let mut cycles = vec![None; bus.clock - start];
+    for &(cycle, access) in log.borrow().iter() {
+        if let Some(slot) = cycle.checked_sub(1).and_then(|i| cycles.get_mut(i)) {
+            *slot = Some(access);
+        }
+    }
+
+    let mut errors = vec![];
+    let r = &cpu.registers;
+    let registers = [
+        ("a", r.a as u16),
+        ("f", r.f as u16),
+        ("b", r.b as u16),
+        ("c", r.c as u16),
+        ("d", r.d as u16),
+        ("e", r.e as u16),
+        ("h", r.h as u16),
+        ("l", r.l as u16),
+        ("sp", r.sp),
+        ("pc", r.pc),
+        // EI's delay still counts as enabled.
+        ("ime", (bus.ime != 0 || cpu.ei_delay) as u16),
+    ];
+    for (name, value) in registers.iter() {
+        if expected[*name].is_u64() && field(expected, name) != *value {
+            errors.push(format!(
+                "{} was {:02x}, expected {:02x}",
+                name,
+                value,
+                field(expected, name)
+            ));
+        }
+    }
+    for (address, value) in ram(expected) {
+        let actual = bus.read(address);
+        if actual != value {
+            errors.push(format!(
+                "[{:04x}] was {:02x}, expected {:02x}",
+                address, actual, value
+            ));
+        }
+    }
+    let expected_cycles: Vec<_> = test["cycles"]
+        .as_array()
+        .map(|cycles| cycles.iter().map(expected_access).collect())
+        .unwrap_or_default();
+    if cycles != expected_cycles {
+        errors.push(format!(
+            "cycles were {:?}, expected {:?}",
+            cycles, expected_cycles
+        ));
+    }
+
+    if errors.is_empty() {
+        Ok(())
+    } else {
+        Err(errors.join(", "))
+    }
+}
+
+#[test]
+#[ignore = "the SM83 tests aren't checked in, set SM83_TESTS or copy them to test_roms/sm83"]
+fn sm83_single_step() {
+    let dir =
+        PathBuf::from(std::env::var("SM83_TESTS").unwrap_or_else(|_| "test_roms/sm83".into()));
+    let mut files: Vec<_> = fs::read_dir(&dir)
+        .unwrap_or_else(|e| panic!("{}: {}", dir.display(), e))
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.extension().is_some_and(|ext| ext == "json"))
+        .collect();
+    assert!(!files.is_empty(), "No SM83 tests in {}", dir.display());
+    files.sort();
+
+    // Every opcode gets reported, with the first test of it that failed.
+    let mut failures = vec![];
+    for path in &files {
+        let json: Json = serde_json::from_slice(&fs::read(path).unwrap()).unwrap();
+        let tests = json.as_array().unwrap();
+        let mut failed = tests
+            .iter()
+            .filter_map(|test| run(test).err().map(|e| (test["name"].clone(), e)));
+        if let Some((name, error)) = failed.next() {
+            let count = 1 + failed.count();
+            failures.push(format!(
+                "{}: {}/{} failed, first {}: {}",
+                path.file_name().unwrap().to_string_lossy(),
+                count,
+                tests.len(),
+                name,
+                error
+            ));
+        }
+    }
+    assert!(
+        failures.is_empty(),
+        "{} of {} opcodes failed:\n{}",
+        failures.len(),
+        files.len(),
+        failures.join("\n")
+    );
+}
+
+#[test]
+fn single_step_harness() {
+    // LD (BC),A
+    let test = serde_json::json!({
+        "name": "02 0000",
+        "initial": {
+            "a": 0x42, "b": 0xC0, "c": 0x00, "d": 0, "e": 0, "f": 0xB0, "h": 0, "l": 0,
+            "pc": 0x0101, "sp": 0xFFFE, "ime": 0, "ie": 0,
+            "ram": [[0x0100, 0x02], [0x0101, 0xFA]]
+        },
+        "final": {
+            "a": 0x42, "b": 0xC0, "c": 0x00, "d": 0, "e": 0, "f": 0xB0, "h": 0, "l": 0,
+            "pc": 0x0102, "sp": 0xFFFE, "ime": 0,
+            "ram": [[0x0100, 0x02], [0x0101, 0xFA], [0xC000, 0x42]]
+        },
+        "cycles": [[0xC000, 0x42, "-wm"], [0x0101, 0xFA, "r-m"]]
+    });
+    assert_eq!(run(&test), Ok(()));
+
+    let mut wrong = test.clone();
+    wrong["final"]["ram"][2][1] = 0x43.into();
+    wrong["cycles"][0] = serde_json::Value::Null;
+    assert_eq!(
+        run(&wrong),
+        Err(
+            "[c000] was 42, expected 43, cycles were [Some(Write(49152, 66)), \
+             Some(Read(257, 250))], expected [None, Some(Read(257, 250))]"
+                .into()
+        )
+    );
+}
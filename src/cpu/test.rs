@@ -1,5 +1,6 @@
 use super::*;
-use crate::instructions::{Instr, Location::*};
+use crate::bus::Memory;
+use crate::instructions::{Instr, Location::*, Register::*, Register16::*};
 
 //https://github.com/CTurt/Cinoop/blob/990e7d92b759892e98a450b4979e887865d6757f/source/cpu.c
 // TODO, Add tests that have variable tick timings.
@@ -54,20 +55,50 @@ fn time_instr(instr: Instr, cpu: &mut CPU, bus: &mut Bus) -> usize {
     after - before
 }
 
+#[test]
+fn decode_round_trip() {
+    const ILLEGAL: [u8; 11] = [
+        0xD3, 0xDB, 0xDD, 0xE3, 0xE4, 0xEB, 0xEC, 0xED, 0xF4, 0xFC, 0xFD,
+    ];
+    for op in 0x00..=0xFF {
+        let instr = INSTR_TABLE[op as usize];
+        assert_eq!(
+            instr == Instr::UNIMPLEMENTED,
+            ILLEGAL.contains(&op),
+            "{:02x} {:?}",
+            op,
+            instr
+        );
+        if instr != Instr::UNIMPLEMENTED {
+            let encoded: u8 = instr.into();
+            assert_eq!(encoded, op, "{:?} encodes to {:02x}", instr, encoded);
+        }
+        assert_eq!(
+            instr.data_length(),
+            INSTR_DATA_LENGTHS[op as usize],
+            "{:02x} {:?}",
+            op,
+            instr
+        );
+        let cb = CB_TABLE[op as usize];
+        let encoded: u8 = cb.into();
+        assert_eq!(encoded, op, "CB {:?} encodes to {:02x}", cb, encoded);
+    }
+}
+
 #[test]
 fn ticks_cb_instr() {
     for instr in 0x00..=0xFF {
         let mut cpu = CPU::new();
-        let mut bus = Bus::new(vec![], None);
+        let mut bus = Bus::new(vec![instr], None);
         let before = bus.clock;
         cpu.registers.pc = 0;
         bus.in_bios = 1;
-        bus.memory[0x00] = instr;
         bus.generic_cycle();
         cpu.opcode = Instr::CB.into();
         cpu.execute_op(&mut bus);
         let after = bus.clock;
-        if let Location::Register(_) = CPU::cb_location(instr) {
+        if let Location::Register(_) = CB_TABLE[instr as usize].location() {
             assert_eq!(after - before, 2, "Opcode failed: {:02x}", instr);
         } else {
             assert_eq!(after - before, 4, "Opcode failed: {:02x}", instr);
@@ -178,19 +209,19 @@ fn pop_af() {
     cpu.registers.h = 0xF0;
     for i in 0..0xFF {
         // -    push bc
-        let opcode = Instr::PUSH(Register(BC)).into();
+        let opcode = Instr::PUSH(BC).into();
         cpu.opcode = opcode;
         cpu.execute_op(&mut bus);
         //      pop  af
-        let opcode = Instr::POP(Register(AF)).into();
+        let opcode = Instr::POP(AF).into();
         cpu.opcode = opcode;
         cpu.execute_op(&mut bus);
         //      push af
-        let opcode = Instr::PUSH(Register(AF)).into();
+        let opcode = Instr::PUSH(AF).into();
         cpu.opcode = opcode;
         cpu.execute_op(&mut bus);
         //      pop  de
-        let opcode = Instr::POP(Register(DE)).into();
+        let opcode = Instr::POP(DE).into();
         cpu.opcode = opcode;
         cpu.execute_op(&mut bus);
         //      ld   a,c
@@ -219,3 +250,389 @@ fn pop_af() {
         cpu.execute_op(&mut bus);
     }
 }
+
+#[test]
+fn illegal_opcode_locks() {
+    let mut cpu = CPU::new();
+    let mut bus = Bus::new(vec![], None);
+    bus.rom_start_signal = false;
+    cpu.registers.pc = 0x1235;
+    cpu.op_addr = 0x1234;
+    cpu.opcode = 0xDD;
+    cpu.step(&mut bus);
+    assert_eq!(cpu.state, CPUState::Locked);
+    // Locked CPUs ignore interrupts but time keeps passing.
+    bus.ime = 1;
+    bus.int_enabled = VBLANK;
+    bus.int_flags = VBLANK;
+    let before = bus.clock;
+    cpu.step(&mut bus);
+    assert_eq!(bus.clock, before + 1);
+    assert_eq!(cpu.registers.pc, 0x1235);
+    assert_eq!(cpu.op_addr, 0x1234);
+}
+
+fn stack_top(cpu: &CPU, bus: &Bus) -> u16 {
+    let sp = cpu.registers.sp;
+    u16::from_le_bytes([bus.read(sp), bus.read(sp.wrapping_add(1))])
+}
+
+fn halt_program(program: &[u8]) -> (CPU, Bus) {
+    let mut cpu = CPU::new();
+    let mut bus = Bus::new(program.to_vec(), None);
+    bus.rom_start_signal = false;
+    cpu.registers.sp = 0xFFFE;
+    cpu.state = cpu.prefetch_op(&mut bus, 0);
+    (cpu, bus)
+}
+
+#[test]
+fn halt() {
+    // halt; inc a
+    let (mut cpu, mut bus) = halt_program(&[0x76, 0x3C]);
+    bus.int_enabled = TIMER;
+    cpu.step(&mut bus);
+    assert_eq!(cpu.state, CPUState::Halted);
+    for _ in 0..10 {
+        cpu.step(&mut bus);
+    }
+    assert_eq!(cpu.state, CPUState::Halted);
+    // Wakes up without IME, and just carries on.
+    bus.int_flags = TIMER;
+    cpu.step(&mut bus);
+    cpu.step(&mut bus);
+    assert_eq!(cpu.registers.a, 1);
+    assert_eq!(bus.int_flags, TIMER);
+
+    // With IME the interrupt is serviced, returning after the HALT.
+    let (mut cpu, mut bus) = halt_program(&[0x76, 0x3C]);
+    bus.int_enabled = TIMER;
+    bus.ime = 1;
+    cpu.step(&mut bus);
+    bus.int_flags = TIMER;
+    cpu.step(&mut bus);
+    cpu.step(&mut bus);
+    assert_eq!(cpu.registers.pc, 0x51);
+    assert_eq!(stack_top(&cpu, &bus), 0x01);
+}
+
+#[test]
+fn halt_bug() {
+    // halt; inc a; the inc is executed twice.
+    let (mut cpu, mut bus) = halt_program(&[0x76, 0x3C, 0x00]);
+    bus.int_enabled = VBLANK;
+    bus.int_flags = VBLANK;
+    cpu.step(&mut bus);
+    assert_eq!(cpu.state, CPUState::Running);
+    cpu.step(&mut bus);
+    cpu.step(&mut bus);
+    assert_eq!(cpu.registers.a, 2);
+    assert_eq!(cpu.registers.pc, 3);
+}
+
+// Runs a blargg test ROM until it reports a result, either over serial or,
+// for the later ROMs, as text at $A004 once the status at $A000 is no longer
+// $80. Those mark it with DE B0 61 at $A001.
+fn run_blargg(rom: &[u8]) -> String {
+    let mut cpu = CPU::new();
+    let mut bus = Bus::new(rom.to_vec(), None);
+    loop {
+        if bus.io.contains("Passed") || bus.io.contains("Failed") {
+            return bus.io;
+        }
+        let signature = [bus.read(0xA001), bus.read(0xA002), bus.read(0xA003)];
+        if signature == [0xDE, 0xB0, 0x61] && bus.read(0xA000) != 0x80 {
+            return (0xA004..0xC000)
+                .map(|address| bus.read(address))
+                .take_while(|c| *c != 0)
+                .map(char::from)
+                .collect();
+        }
+        cpu.step(&mut bus);
+        assert!(bus.clock < 10_000_000, "Timed out: {}", bus.io);
+    }
+}
+
+#[test]
+fn blargg_interrupts() {
+    let output = run_blargg(include_bytes!("../../test_roms/02-interrupts.gb"));
+    assert!(output.ends_with("Passed"), "{}", output);
+}
+
+#[test]
+fn blargg_memory_protocol() {
+    // MBC1 with RAM: enable RAM, then write the signature, the text and
+    // finally a status of 0 byte by byte with LD A,n; LD (nn),A.
+    let mut rom = vec![0; 0x8000];
+    rom[0x147] = 0x03;
+    rom[0x149] = 0x02;
+    let mut writes = vec![(0x0000, 0x0A), (0xA000, 0x80)];
+    let text = b"\xDE\xB0\x61Passed\0";
+    writes.extend(text.iter().enumerate().map(|(i, b)| (0xA001 + i, *b)));
+    writes.push((0xA000, 0));
+    let mut program = vec![];
+    for (address, value) in writes {
+        program.extend_from_slice(&[0x3E, value, 0xEA, address as u8, (address >> 8) as u8]);
+    }
+    program.extend_from_slice(&[0x18, 0xFE]);
+    // JP $0150, past the header.
+    rom[0x100..0x103].copy_from_slice(&[0xC3, 0x50, 0x01]);
+    rom[0x150..0x150 + program.len()].copy_from_slice(&program);
+    assert_eq!(run_blargg(&rom), "Passed");
+}
+
+#[test]
+#[ignore = "halt_bug.gb isn't checked in, copy it to test_roms/halt_bug.gb"]
+fn blargg_halt_bug() {
+    let rom = std::fs::read("test_roms/halt_bug.gb").expect("test_roms/halt_bug.gb");
+    let output = run_blargg(&rom);
+    assert!(output.contains("Passed"), "{}", output);
+}
+
+// Runs a mooneye test ROM until it signals with LD B,B, returning whether it
+// passed: B, C, D, E, H and L then hold 3, 5, 8, 13, 21 and 34.
+fn run_mooneye(rom: &[u8]) -> bool {
+    let mut cpu = CPU::new();
+    let mut bus = Bus::new(rom.to_vec(), None);
+    while bus.clock < 10_000_000 {
+        cpu.step(&mut bus);
+        if cpu.opcode == 0x40 {
+            let r = &cpu.registers;
+            return [r.b, r.c, r.d, r.e, r.h, r.l] == [3, 5, 8, 13, 21, 34];
+        }
+    }
+    false
+}
+
+#[test]
+fn mooneye_harness() {
+    let program = |value: u8| {
+        let mut rom = vec![0; 0x8000];
+        let mut fib = vec![];
+        for (i, op) in [0x06, 0x0E, 0x16, 0x1E, 0x26, 0x2E].iter().enumerate() {
+            fib.extend_from_slice(&[*op, [3, 5, 8, 13, 21, value][i]]);
+        }
+        fib.push(0x40);
+        rom[0x100..0x100 + fib.len()].copy_from_slice(&fib);
+        rom
+    };
+    assert!(run_mooneye(&program(34)));
+    assert!(!run_mooneye(&program(0x42)));
+    // Never signalling is a failure too.
+    assert!(!run_mooneye(&[0; 0x8000]));
+}
+
+#[test]
+#[ignore = "mooneye's acceptance/timer ROMs aren't checked in, copy them to test_roms/mooneye/timer/"]
+fn mooneye_timer() {
+    let names = [
+        "tima_reload",
+        "tima_write_reloading",
+        "tma_write_reloading",
+        "rapid_toggle",
+        "div_write",
+        "tim00",
+        "tim00_div_trigger",
+        "tim01",
+        "tim01_div_trigger",
+        "tim10",
+        "tim10_div_trigger",
+        "tim11",
+        "tim11_div_trigger",
+    ];
+    let failed: Vec<_> = names
+        .iter()
+        .filter(|name| {
+            let path = format!("test_roms/mooneye/timer/{}.gb", name);
+            let rom = std::fs::read(&path).unwrap_or_else(|e| panic!("{}: {}", path, e));
+            !run_mooneye(&rom)
+        })
+        .collect();
+    assert!(failed.is_empty(), "Failed: {:?}", failed);
+}
+
+#[test]
+fn stop() {
+    // stop; inc a
+    let (mut cpu, mut bus) = halt_program(&[0x10, 0x00, 0x3C]);
+    bus.timer.internal = 0x1234;
+    cpu.step(&mut bus);
+    assert_eq!(cpu.state, CPUState::Stopped);
+    assert_eq!(cpu.registers.pc, 2, "STOP should skip its second byte");
+    assert_eq!(bus.timer.div(), 0);
+    let before = bus.clock;
+    for _ in 0..100 {
+        cpu.step(&mut bus);
+    }
+    assert_eq!(bus.clock, before + 100);
+    assert_eq!(bus.timer.internal, 0, "DIV shouldn't tick while stopped");
+    assert_eq!(cpu.state, CPUState::Stopped);
+    // Pressing a button on the selected line wakes it up.
+    bus.keypresses &= !0b1000;
+    cpu.step(&mut bus);
+    cpu.step(&mut bus);
+    assert_eq!(cpu.registers.a, 1);
+
+    // With a button held it only halts.
+    let (mut cpu, mut bus) = halt_program(&[0x10, 0x00, 0x3C]);
+    bus.keypresses &= !0b1000;
+    cpu.step(&mut bus);
+    assert_eq!(cpu.state, CPUState::Halted);
+    assert_eq!(cpu.registers.pc, 2);
+}
+
+#[test]
+fn stop_speed_switch() {
+    let (mut cpu, mut bus) = halt_program(&[0x10, 0x00, 0x3C]);
+    bus.write(0xFF4D, 0x01);
+    assert_eq!(bus.read(0xFF4D), 0xFF, "KEY1 doesn't exist on DMG");
+    bus.model = crate::model::Model::Cgb;
+    bus.write(0xFF4D, 0x01);
+    assert_eq!(bus.read(0xFF4D), 0x7F);
+    cpu.step(&mut bus);
+    assert_eq!(cpu.state, CPUState::Running);
+    assert_eq!(bus.read(0xFF4D), 0xFE);
+}
+
+#[test]
+fn ei_delay() {
+    // ei; inc a; inc a
+    let (mut cpu, mut bus) = halt_program(&[0xFB, 0x3C, 0x3C]);
+    bus.int_enabled = TIMER;
+    bus.int_flags = TIMER;
+    cpu.step(&mut bus);
+    assert_eq!(cpu.state, CPUState::Running);
+    cpu.step(&mut bus);
+    assert_eq!(cpu.registers.a, 1, "The instruction after EI runs first");
+    assert_eq!(cpu.state, CPUState::Interrupted);
+    // ei; di never lets an interrupt through.
+    let (mut cpu, mut bus) = halt_program(&[0xFB, 0xF3, 0x3C]);
+    bus.int_enabled = TIMER;
+    bus.int_flags = TIMER;
+    cpu.step(&mut bus);
+    cpu.step(&mut bus);
+    assert_eq!(cpu.state, CPUState::Running);
+    assert_eq!(bus.ime, 0);
+}
+
+#[test]
+fn interrupt_dispatch() {
+    let (mut cpu, mut bus) = halt_program(&[0x00]);
+    cpu.registers.pc = 0x1234;
+    bus.ime = 1;
+    bus.int_enabled = TIMER | SERIAL;
+    bus.int_flags = TIMER | SERIAL;
+    cpu.state = CPUState::Interrupted;
+    let before = bus.clock;
+    cpu.step(&mut bus);
+    assert_eq!(bus.clock - before, 5);
+    assert_eq!(cpu.op_addr, 0x50);
+    assert_eq!(bus.int_flags, SERIAL);
+    assert_eq!(bus.ime, 0);
+    assert_eq!(stack_top(&cpu, &bus), 0x1234);
+}
+
+#[test]
+fn ie_push() {
+    // Pushing PC's high byte to IE at 0xFFFF cancels the interrupt...
+    let (mut cpu, mut bus) = halt_program(&[0x00]);
+    cpu.registers.sp = 0x0000;
+    cpu.registers.pc = 0x0200;
+    bus.ime = 1;
+    bus.int_enabled = VBLANK;
+    bus.int_flags = VBLANK;
+    cpu.state = CPUState::Interrupted;
+    cpu.step(&mut bus);
+    assert_eq!(cpu.op_addr, 0x0000);
+    assert_eq!(bus.int_flags, VBLANK);
+    // ...or redirects it to another one that is still enabled.
+    let (mut cpu, mut bus) = halt_program(&[0x00]);
+    cpu.registers.sp = 0x0000;
+    cpu.registers.pc = 0x0200;
+    bus.ime = 1;
+    bus.int_enabled = VBLANK;
+    bus.int_flags = VBLANK | LCDSTAT;
+    cpu.state = CPUState::Interrupted;
+    cpu.step(&mut bus);
+    assert_eq!(cpu.op_addr, 0x0048);
+    assert_eq!(bus.int_flags, VBLANK);
+}
+
+#[test]
+fn tick() {
+    // ld (hl), $42; inc a
+    let (mut cpu, mut bus) = halt_program(&[0x36, 0x42, 0x3C]);
+    cpu.registers.h = 0xC0;
+    let before = bus.clock;
+    cpu.tick(&mut bus);
+    assert_eq!(cpu.cycle, 1);
+    assert_eq!(cpu.registers.pc, 2, "Operand read");
+    assert_eq!(bus.read(0xC000), 0x00);
+    cpu.tick(&mut bus);
+    assert_eq!(cpu.cycle, 2);
+    assert_eq!(bus.read(0xC000), 0x42, "Written mid-instruction");
+    assert_eq!(cpu.opcode, 0x36);
+    cpu.tick(&mut bus);
+    assert_eq!(cpu.cycle, 0);
+    assert_eq!(cpu.opcode, 0x3C);
+    assert_eq!(bus.clock - before, 3);
+
+    // Pushes are resumed without moving SP twice.
+    // call $0010
+    let (mut cpu, mut bus) = halt_program(&[0xCD, 0x10, 0x00]);
+    for i in 1..7 {
+        cpu.tick(&mut bus);
+        assert_eq!(bus.clock, i + 1);
+    }
+    assert_eq!(cpu.op_addr, 0x10);
+    assert_eq!(cpu.registers.sp, 0xFFFC);
+    assert_eq!(stack_top(&cpu, &bus), 0x0003);
+}
+
+#[test]
+fn post_boot_state() {
+    let mut rom = vec![0; 0x8000];
+    rom[0x0104] = 0xCE;
+    rom[0x014D] = 0x00;
+    let mut bus = Bus::new(rom, None);
+    let mut cpu = CPU::new();
+    cpu.load_start_values(&mut bus);
+    assert_eq!(
+        (cpu.registers.a, cpu.registers.f, cpu.registers.pc),
+        (0x01, 0x80, 0x0100),
+        "A zero header checksum leaves H and C clear"
+    );
+    assert_eq!(bus.timer.div(), 0xAB);
+    assert_eq!(bus.int_flags, VBLANK);
+    // 0xCE blown up: 1100 -> 11110000, 1110 -> 11111100, each row twice.
+    let tile: Vec<u8> = (0x8010..0x8018).map(|a| bus.read(a)).collect();
+    assert_eq!(tile, [0xF0, 0x00, 0xF0, 0x00, 0xFC, 0x00, 0xFC, 0x00]);
+    assert_eq!(bus.read(0x8190), 0x3C);
+    assert_eq!(bus.read(0x9904), 0x01);
+    assert_eq!(bus.read(0x992F), 0x18);
+    assert_eq!(bus.read(0x9910), 0x19);
+
+    let mut bus = Bus::new(vec![0; 0x8000], None);
+    bus.model = crate::model::Model::Cgb;
+    cpu.load_start_values(&mut bus);
+    assert_eq!((cpu.registers.a, cpu.registers.d), (0x11, 0xFF));
+    assert_eq!(bus.read(0x8010), 0x00, "No DMG logo on CGB");
+}
+
+#[test]
+fn boot_rom_handoff_keeps_state() {
+    let mut cpu = CPU::new();
+    let mut bus = Bus::new(vec![0; 0x8000], None);
+    bus.in_bios = 0;
+    bus.rom_start_signal = false;
+    // ld a, $42; ldh ($50), a
+    bus.bootrom[..4].copy_from_slice(&[0x3E, 0x42, 0xE0, 0x50]);
+    cpu.state = cpu.prefetch_op(&mut bus, 0);
+    cpu.step(&mut bus);
+    cpu.step(&mut bus);
+    cpu.step(&mut bus);
+    assert_eq!(bus.in_bios, 0x42);
+    assert_eq!(cpu.registers.a, 0x42);
+    assert_eq!(cpu.op_addr, 0x0005);
+    assert_eq!(bus.read(0x8010), 0);
+}
@@ -6,12 +6,69 @@ use sdl2::{event::Event, video::GLContext};
 use imgui::{Context, Slider, Ui};
 use crate::constants::MaybeErr;
 use crate::emu::InstrListing;
-use imgui::im_str;
+use crate::cheats::Cheats;
+use imgui::{im_str, CollapsingHeader, ImString};
 
 #[derive(Default)]
 pub struct Info {
     pub frame_times: VecDeque<f32>,
     pub il: Vec<InstrListing>,
+    pub cheat_code: ImString,
+    pub cheat_name: ImString,
+    pub cheat_error: Option<String>,
+}
+
+// Lists the cheats with toggles, and lets new codes be entered.
+pub fn cheats_ui(info: &mut Info, ui: &Ui, cheats: &mut Cheats) {
+    if !CollapsingHeader::new(im_str!("Cheats")).build(ui) {
+        return;
+    }
+    let mut remove = None;
+    for (i, cheat) in cheats.list.iter_mut().enumerate() {
+        let id = ui.push_id(i as i32);
+        let label = ImString::new(format!("{} {}", cheat.code, cheat.name));
+        ui.checkbox(&label, &mut cheat.enabled);
+        ui.same_line(0.0);
+        if ui.small_button(im_str!("Remove")) {
+            remove = Some(i);
+        }
+        id.pop(ui);
+    }
+    if let Some(i) = remove {
+        cheats.remove(i);
+    }
+    ui.input_text(im_str!("Code"), &mut info.cheat_code).build();
+    ui.input_text(im_str!("Name"), &mut info.cheat_name).build();
+    if ui.small_button(im_str!("Add cheat")) {
+        info.cheat_error = match cheats.add(info.cheat_code.to_str(), info.cheat_name.to_str()) {
+            Ok(()) => {
+                info.cheat_code.clear();
+                info.cheat_name.clear();
+                None
+            }
+            Err(e) => Some(e.to_string()),
+        };
+    }
+    if let Some(e) = &info.cheat_error {
+        ui.text(e);
+    }
+}
+
+// Disassembly of the ROM around `pc`, with the current instruction marked.
+pub fn disassembly_ui(info: &Info, ui: &Ui, pc: u16) {
+    if !CollapsingHeader::new(im_str!("Disassembly")).build(ui) {
+        return;
+    }
+    match info.il.iter().position(|il| il.addr == pc) {
+        Some(i) => {
+            let start = i.saturating_sub(5);
+            for il in info.il.iter().skip(start).take(10) {
+                let marker = if il.addr == pc { ">" } else { " " };
+                ui.text(format!("{} {}", marker, il));
+            }
+        }
+        None => ui.text(format!("PC {:04x} is not in the ROM listing", pc)),
+    }
 }
 
 pub struct Imgui<'a> {
@@ -38,7 +95,11 @@ impl<'a> Imgui<'a> {
             renderer,
             window,
             _gl_context,
-            info: Default::default(),
+            info: Info {
+                cheat_code: ImString::with_capacity(16),
+                cheat_name: ImString::with_capacity(64),
+                ..Default::default()
+            },
         })
     }
     pub fn capture_io(&mut self, event_pump: &mut sdl2::EventPump) {
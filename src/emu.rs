@@ -1,14 +1,20 @@
-use std::{error::Error, fs::File, io::Read, path::PathBuf};
+use std::{error::Error, fmt::Display, fs, fs::File, io, io::Read, io::Write, path::PathBuf};
 
-use crate::bus::Bus;
-use crate::instructions::Instr;
+use crate::bus::{Bus, Memory};
+use crate::cartridge::header::Header;
+use crate::cheats::Cheats;
+use crate::constants::GB_CYCLE_SPEED;
+use crate::instructions::{CBInstr, Instr, CB_TABLE};
 use crate::instructions::INSTR_DATA_LENGTHS;
 use crate::instructions::INSTR_TABLE;
-use crate::{cpu::CPU, gpu::PixelData};
+use crate::patch;
+use crate::{cpu::CPUState, cpu::CPU, gpu::FRAME_CYCLES};
 
 #[derive(Clone, Debug, Default)]
 pub struct InstrListing {
     pub instr: Instr,
+    // Decoded second byte of a 0xCB prefixed instruction.
+    pub cb: Option<CBInstr>,
     pub data: Option<u16>,
     pub addr: u16,
 }
@@ -22,14 +28,20 @@ pub fn gen_il(mem: &[u8]) -> Vec<InstrListing> {
         let op = mem[i];
         let instr = INSTR_TABLE[op as usize];
         let data_length = INSTR_DATA_LENGTHS[op as usize];
-        let data = match data_length {
-            0 => None,
-            1 => Some(mem[i + 1] as u16),
-            2 => Some(u16::from_le_bytes([mem[i + 1], mem[i + 2]])),
+        // Cut off by the end of memory.
+        if i + data_length >= mem.len() {
+            break;
+        }
+        let (data, cb) = match (instr, data_length) {
+            (Instr::CB, _) => (None, Some(CB_TABLE[mem[i + 1] as usize])),
+            (_, 0) => (None, None),
+            (_, 1) => (Some(mem[i + 1] as u16), None),
+            (_, 2) => (Some(u16::from_le_bytes([mem[i + 1], mem[i + 2]])), None),
             _ => unreachable!(),
         };
         view.push(InstrListing {
             instr,
+            cb,
             data,
             addr: i as u16,
         });
@@ -39,24 +51,99 @@ pub fn gen_il(mem: &[u8]) -> Vec<InstrListing> {
 }
 
 pub fn str_il(il: &[InstrListing]) -> String {
-    il.iter().fold(String::new(), |res, il| {
-        res + &format!("{:04x}: {:?} {:?}\n", il.addr, il.instr, il.data)
-    })
+    il.iter()
+        .fold(String::new(), |res, il| res + &format!("{}\n", il))
+}
+
+impl Display for InstrListing {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (self.cb, self.data) {
+            (Some(cb), _) => write!(f, "{:04x}: {}", self.addr, cb),
+            (None, Some(data)) => write!(f, "{:04x}: {:?} {:04x}", self.addr, self.instr, data),
+            (None, None) => write!(f, "{:04x}: {:?}", self.addr, self.instr),
+        }
+    }
 }
 
+// Things happening inside the machine that the frontend may want to react to.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Event {
+    Rumble(bool),
+    // The CPU ran an illegal opcode at this address and hung.
+    Locked(u16),
+}
+
+type EventListener = dyn FnMut(&Event);
+
+// How often battery backed RAM is flushed to disk while running, if it changed:
+// every five minutes of emulated time, counted in M-cycles like `bus.clock`.
+pub const SAVE_INTERVAL: usize = GB_CYCLE_SPEED / 4 * 60 * 5;
+
 // Global emu struct.
 pub struct Emu {
     pub cpu: CPU,
     pub bus: Bus,
-    pub framebuffer: Box<PixelData>,
     prev: CPU,
+    rumble: bool,
+    listeners: Vec<Box<EventListener>>,
+    pub save_path: Option<PathBuf>,
+    pub cheat_path: Option<PathBuf>,
+    saved_ram: Vec<u8>,
+    last_save: usize,
+    // Gets a Gameboy Doctor line for every instruction run, see `trace_to`.
+    trace: Option<Box<dyn Write>>,
 }
 
 impl Emu {
     pub fn emulate_step(&mut self) {
         self.prev = self.cpu.clone();
-        println!("{}", self.cpu);
         self.cpu.step(&mut self.bus);
+        self.after_cycles();
+    }
+
+    // Advances the machine by a single M-cycle, possibly stopping mid-instruction.
+    pub fn emulate_cycle(&mut self) {
+        self.prev = self.cpu.clone();
+        self.cpu.tick(&mut self.bus);
+        self.after_cycles();
+    }
+
+    // Runs for exactly `cycles` M-cycles.
+    pub fn run_cycles(&mut self, cycles: usize) {
+        let end = self.bus.clock + cycles;
+        while self.bus.clock < end {
+            self.emulate_cycle();
+        }
+    }
+
+    // Runs until the LCD has finished a frame, or for as long as one takes
+    // while it's off. Stops on the M-cycle the frame ends, even mid-instruction.
+    pub fn run_frame(&mut self) {
+        let end = self.bus.clock + FRAME_CYCLES;
+        while !self.bus.gpu.take_frame() && self.bus.clock < end {
+            self.emulate_cycle();
+        }
+    }
+
+    // Logs every instruction from here on to `sink`, in the format Gameboy Doctor
+    // diffs against its reference logs. Wrap files in a `BufWriter`. LY reads
+    // as 0x90 while tracing, as it does for the reference logs.
+    pub fn trace_to<W: 'static + Write>(&mut self, sink: W) {
+        self.trace = Some(Box::new(sink));
+        self.bus.doctor = true;
+    }
+
+    fn after_cycles(&mut self) {
+        self.check_events();
+        self.write_trace();
+        if self.bus.clock >= self.last_save + SAVE_INTERVAL {
+            self.last_save = self.bus.clock;
+            if self.bus.cartridge.ram() != &self.saved_ram[..] {
+                if let Err(e) = self.save() {
+                    println!("Couldn't write save file: {}", e);
+                }
+            }
+        }
     }
 
     pub fn new(rom: Vec<u8>, bootrom: Option<PathBuf>) -> Emu {
@@ -66,65 +153,245 @@ impl Emu {
         Emu {
             cpu,
             bus,
-            framebuffer: Box::new([[0; 256]; 256]),
             prev,
+            rumble: false,
+            listeners: vec![],
+            save_path: None,
+            cheat_path: None,
+            saved_ram: vec![],
+            last_save: 0,
+            trace: None,
         }
     }
 
-    pub fn from_path(input: PathBuf, bootrom: Option<PathBuf>) -> Result<Emu, Box<dyn Error>> {
-        let mut file = File::open(input)?;
+    // Loads the ROM at `input`, applying each IPS, UPS or BPS patch in `patches` in order.
+    pub fn from_path(
+        input: PathBuf,
+        bootrom: Option<PathBuf>,
+        patches: &[PathBuf],
+    ) -> Result<Emu, Box<dyn Error>> {
+        let mut file = File::open(&input)?;
         let mut rom = Vec::new();
         file.read_to_end(&mut rom)?;
-        let mut cpu = CPU::new();
-        let bus = Bus::new(rom, bootrom);
-        let prev = cpu.clone();
-        Ok(Emu {
-            cpu,
-            bus,
-            framebuffer: Box::new([[0; 256]; 256]),
-            prev,
-        })
+        for path in patches {
+            rom = patch::apply(&rom, &fs::read(path)?)
+                .map_err(|e| format!("{}: {}", path.display(), e))?;
+        }
+        let header = Header::parse(&rom)?;
+        if let Err(e) = header.verify_global_checksum(&rom) {
+            println!("{}", e);
+        }
+        let mut emu = Emu::new(rom, bootrom);
+        if emu.bus.cartridge.has_battery() {
+            emu.load_save(input.with_extension("sav"))?;
+        }
+        // Cheats are optional, a broken file shouldn't keep the game from booting.
+        if let Err(e) = emu.load_cheats(input.with_extension("cht")) {
+            println!("Couldn't load cheats, starting without them: {}", e);
+        }
+        Ok(emu)
+    }
+
+    // Loads battery backed RAM from `path`, which is also where `save` writes to.
+    // A missing file just means there's no save yet.
+    pub fn load_save(&mut self, path: PathBuf) -> io::Result<()> {
+        match fs::read(&path) {
+            Ok(data) => self.bus.cartridge.load_save_data(&data),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => {}
+            Err(e) => return Err(e),
+        }
+        self.saved_ram = self.bus.cartridge.ram().to_vec();
+        self.save_path = Some(path);
+        Ok(())
+    }
+
+    pub fn save(&mut self) -> io::Result<()> {
+        if let Some(path) = &self.save_path {
+            fs::write(path, self.bus.cartridge.save_data())?;
+            self.saved_ram = self.bus.cartridge.ram().to_vec();
+        }
+        Ok(())
+    }
+
+    // Loads the cheat list from `path`, which is also where `save_cheats` writes to.
+    pub fn load_cheats(&mut self, path: PathBuf) -> Result<(), Box<dyn Error>> {
+        if path.exists() {
+            self.bus.cheats = Cheats::load(&path)?;
+        }
+        self.cheat_path = Some(path);
+        Ok(())
+    }
+
+    // Only writes a file once there's something in it.
+    pub fn save_cheats(&self) -> io::Result<()> {
+        match &self.cheat_path {
+            Some(path) if !self.bus.cheats.list.is_empty() || path.exists() => {
+                self.bus.cheats.save(path)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    pub fn add_listener<F: 'static + FnMut(&Event)>(&mut self, f: F) {
+        self.listeners.push(Box::new(f));
+    }
+
+    fn emit(&mut self, event: Event) {
+        for listener in &mut self.listeners {
+            listener(&event);
+        }
+    }
+
+    // Runs when the next instruction has just been fetched, which is also when
+    // an interrupt dispatch has just finished.
+    fn write_trace(&mut self) {
+        if let Some(sink) = &mut self.trace {
+            if self.cpu.cycle != 0 || self.cpu.state != CPUState::Running {
+                return;
+            }
+            if let Err(e) = writeln!(sink, "{}", self.cpu.doctor_line(&self.bus)) {
+                println!("Couldn't write trace: {}", e);
+                self.trace = None;
+                self.bus.doctor = false;
+            }
+        }
+    }
+
+    fn check_events(&mut self) {
+        let rumble = self.bus.cartridge.rumble();
+        if rumble != self.rumble {
+            self.rumble = rumble;
+            self.emit(Event::Rumble(rumble));
+        }
+        if self.cpu.state == CPUState::Locked && self.prev.state != CPUState::Locked {
+            self.emit(Event::Locked(self.cpu.op_addr));
+        }
     }
 
     pub fn gen_il(&self, mem: &[u8]) -> Vec<InstrListing> {
-        let mut view = vec![];
-        let mut i = 0;
-        while i < mem.len() {
-            let op = mem[i];
-            let instr = INSTR_TABLE[op as usize];
-            let data_length = INSTR_DATA_LENGTHS[op as usize];
-            let data = match data_length {
-                0 => None,
-                1 => Some(mem[i + 1] as u16),
-                2 => Some(u16::from_le_bytes([mem[i + 1], mem[i + 2]])),
-                _ => unreachable!(),
-            };
-            view.push(InstrListing {
-                instr,
-                data,
-                addr: i as u16,
-            });
-            i += 1 + data_length;
-        }
-        view
+        gen_il(mem)
     }
 
+    // The listing around PC, decoded from what the CPU sees at 0000-FFFF, so
+    // code in RAM and the current ROM bank show up too. Empty if decoding from
+    // the start of memory doesn't land on PC.
     pub fn view(&self) -> Vec<InstrListing> {
         let pc = self.cpu.op_addr;
-        let mem = if self.bus.in_bios == 0 {
-            &self.bus.bootrom[..]
-        } else {
-            &self.bus.memory[..]
-        };
+        let mem: Vec<u8> = (0..=0xFFFF).map(|address| self.bus.read(address)).collect();
         let il = gen_il(&mem);
         il.chunks(10)
             .find(|chunk| chunk.iter().any(|e| e.addr == pc))
-            .unwrap_or_else(|| {
-                panic!(
-                    "PC: {:04x} {:?}",
-                    pc, INSTR_TABLE[mem[pc as usize] as usize]
-                )
-            })
-            .to_vec()
+            .map(|chunk| chunk.to_vec())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{cell::RefCell, rc::Rc};
+
+    #[test]
+    fn cb_listing() {
+        let il = gen_il(&[0xCB, 0x7E, 0x00, 0xCB, 0x37, 0x3E, 0x12]);
+        assert_eq!(il.len(), 4);
+        assert_eq!(il[0].data, None);
+        assert_eq!(
+            str_il(&il),
+            "0000: BIT 7,(HL)\n0002: NOOP\n0003: SWAP A\n0005: LD(Register(A), Immediate) 0012\n"
+        );
+    }
+
+    #[test]
+    fn view_uses_mapped_bank() {
+        // 128 KiB MBC1 ROM, all NOPs except for LD A,$12 at the start of bank 2.
+        let mut rom = vec![0; 0x20000];
+        rom[0x147] = 0x01;
+        rom[0x148] = 0x02;
+        rom[0x8000..0x8002].copy_from_slice(&[0x3E, 0x12]);
+        let mut emu = Emu::new(rom, None);
+        emu.bus.write(0x2000, 2);
+        emu.cpu.op_addr = 0x4000;
+        let view = emu.view();
+        assert!(view.iter().any(|il| il.addr == 0x4000 && il.data == Some(0x12)));
+        // Partway into an instruction there's nothing to show, but no panic.
+        emu.cpu.op_addr = 0x4001;
+        assert!(emu.view().is_empty());
+    }
+
+    #[derive(Clone, Default)]
+    struct Log(Rc<RefCell<Vec<u8>>>);
+
+    impl Write for Log {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.borrow_mut().write(buf)
+        }
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn bad_cheat_file() {
+        let dir = std::env::temp_dir().join(format!("rust_emu_cheats_{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let rom = dir.join("game.gb");
+        let mut data = vec![0; 0x8000];
+        data[0x14D] = 0xE7;
+        fs::write(&rom, data).unwrap();
+        fs::write(dir.join("game.cht"), "not a cheat\n").unwrap();
+        let emu = Emu::from_path(rom, None, &[]);
+        fs::remove_dir_all(&dir).unwrap();
+        let emu = emu.unwrap();
+        assert!(emu.bus.cheats.list.is_empty());
+        // Left alone, so the broken file isn't overwritten on exit.
+        assert_eq!(emu.cheat_path, None);
+    }
+
+    #[test]
+    fn run_frame() {
+        // NOP; NOP; JR -4 takes 5 M-cycles, which doesn't divide a frame.
+        let mut rom = vec![0; 0x8000];
+        rom[0x100..0x104].copy_from_slice(&[0x00, 0x00, 0x18, 0xFC]);
+        let mut emu = Emu::new(rom, None);
+        emu.bus.gpu.lcdc = 0x91;
+        emu.run_frame();
+        for _ in 0..10 {
+            let start = emu.bus.clock;
+            emu.run_frame();
+            assert_eq!(emu.bus.clock - start, FRAME_CYCLES);
+        }
+        // With the LCD off it's the same budget.
+        emu.bus.gpu.lcdc = 0;
+        let start = emu.bus.clock;
+        emu.run_frame();
+        assert_eq!(emu.bus.clock - start, FRAME_CYCLES);
+    }
+
+    #[test]
+    fn doctor_trace() {
+        // LD A,$12; LD B,A; LDH A,(LY); JR -2
+        let mut rom = vec![0; 0x8000];
+        rom[0x100..0x107].copy_from_slice(&[0x3E, 0x12, 0x47, 0xF0, 0x44, 0x18, 0xFE]);
+        rom[0x14D] = 0xE7;
+        let mut emu = Emu::new(rom, None);
+        let log = Log::default();
+        emu.trace_to(log.clone());
+        for _ in 0..5 {
+            emu.emulate_step();
+        }
+        let log = String::from_utf8(log.0.borrow().clone()).unwrap();
+        assert_eq!(
+            log,
+            "A:01 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0100 PCMEM:3E,12,47,F0\n\
+             A:12 F:B0 B:00 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0102 PCMEM:47,F0,44,18\n\
+             A:12 F:B0 B:12 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0103 PCMEM:F0,44,18,FE\n\
+             A:90 F:B0 B:12 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0105 PCMEM:18,FE,00,00\n\
+             A:90 F:B0 B:12 C:13 D:00 E:D8 H:01 L:4D SP:FFFE PC:0105 PCMEM:18,FE,00,00\n"
+        );
+        // Without a trace LY is the real one.
+        let mut emu = Emu::new(vec![0; 0x8000], None);
+        emu.emulate_step();
+        assert_ne!(emu.bus.read(0xFF44), 0x90);
     }
 }
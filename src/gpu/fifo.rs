@@ -0,0 +1,250 @@
+// Mode 3, a dot at a time. The background fetcher reads a tile number, then
+// the two bytes of the tile's row, taking 2 dots for each, and pushes the 8
+// pixels once the background FIFO has run dry. Every dot the FIFO has a pixel,
+// one is shifted out to the LCD. Anything that holds up either of them makes
+// mode 3 longer and HBlank shorter.
+use super::*;
+use std::collections::VecDeque;
+
+// Dots a background fetch takes before it can push.
+const FETCH_DOTS: u8 = 6;
+const SPRITE_FETCH_DOTS: u8 = 6;
+// Dots before the first fetch that counts, which makes mode 3 172 dots long
+// without scrolling or sprites.
+const LEAD_IN_DOTS: u8 = 7;
+
+#[derive(Clone, Copy)]
+struct Sprite {
+    y: u8,
+    x: u8,
+    pattern: u8,
+    flags: u8,
+}
+
+#[derive(Clone, Copy, Default)]
+struct SpritePixel {
+    index: u8,
+    obj1: bool,
+    behind: bool,
+}
+
+#[derive(Default)]
+pub struct PixelFifo {
+    // Pixels sent to the LCD so far this line.
+    lx: u8,
+    lead_in: u8,
+    // Pixels still to be thrown away for SCX % 8.
+    discard: u8,
+    bg: VecDeque<u8>,
+    objects: VecDeque<SpritePixel>,
+    // Dots into the current fetch, which map column it's for, and what it read.
+    step: u8,
+    fetch_x: u8,
+    tile: u8,
+    low: u8,
+    high: u8,
+    // Sprites the OAM scan found, by X, and how many have been fetched.
+    sprites: Vec<Sprite>,
+    fetched: usize,
+    // Dots left of the sprite fetch in progress.
+    stall: u8,
+    // Whether the fetcher has switched over to the window this line.
+    window: bool,
+    // These last the whole frame: the window's own line counter, which only
+    // moves on from lines the window was drawn on, and whether LY has hit WY.
+    window_line: u8,
+    wy_hit: bool,
+}
+
+impl GPU {
+    // The OAM scan at the end of mode 2 picks the first 10 sprites on the line.
+    pub(super) fn start_pixel_transfer(&mut self) {
+        let height = self.sprite_height();
+        let line = self.scanline as usize + 16;
+        let fifo = &mut self.fifo;
+        if self.scanline == 0 {
+            fifo.window_line = 0;
+            fifo.wy_hit = false;
+        } else if fifo.window {
+            fifo.window_line += 1;
+        }
+        fifo.window = false;
+        fifo.wy_hit |= self.scanline == self.windowy;
+        fifo.lx = 0;
+        fifo.lead_in = LEAD_IN_DOTS;
+        fifo.discard = self.scrollx % 8;
+        fifo.bg.clear();
+        fifo.objects.clear();
+        fifo.step = 0;
+        fifo.fetch_x = 0;
+        fifo.sprites.clear();
+        fifo.sprites.extend(
+            self.oam[..=OAM_END - OAM_START]
+                .chunks_exact(4)
+                .filter(|s| (s[0] as usize..s[0] as usize + height).contains(&line))
+                .take(10)
+                .map(|s| Sprite {
+                    y: s[0],
+                    x: s[1],
+                    pattern: s[2],
+                    flags: s[3],
+                }),
+        );
+        // Where sprites overlap the one further left wins, then the one earlier
+        // in OAM. Fetching them in that order lets the first one keep the pixel.
+        fifo.sprites.sort_by_key(|s| s.x);
+        fifo.fetched = 0;
+        fifo.stall = 0;
+    }
+
+    // Runs a dot of mode 3, returning true once the last pixel of the line is out.
+    pub(super) fn pixel_transfer_dot(&mut self) -> bool {
+        if self.fifo.lead_in > 0 {
+            self.fifo.lead_in -= 1;
+            return false;
+        }
+        if self.fifo.stall > 0 {
+            self.fifo.stall -= 1;
+            if self.fifo.stall == 0 {
+                self.fetch_sprite();
+            }
+            return false;
+        }
+        if self.window_starts() {
+            // The fetcher starts over on the window's first tile, dropping what
+            // it had. With WX under 7 the window's left edge is off screen.
+            let fifo = &mut self.fifo;
+            fifo.window = true;
+            fifo.bg.clear();
+            fifo.step = 0;
+            fifo.fetch_x = 0;
+            fifo.discard = 7u8.saturating_sub(self.windowx);
+            return false;
+        }
+        self.fetcher_dot();
+        if self.sprite_display_enabled() {
+            let lx = self.fifo.lx;
+            if self
+                .fifo
+                .sprites
+                .get(self.fifo.fetched)
+                .is_some_and(|s| s.x <= lx + 8)
+            {
+                // A sprite waits for the background fetch to reach its last read.
+                if self.fifo.step >= FETCH_DOTS - 1 && !self.fifo.bg.is_empty() {
+                    self.fifo.stall = SPRITE_FETCH_DOTS - 1;
+                }
+                return false;
+            }
+        }
+        self.shift_out()
+    }
+
+    // WX is the window's left edge plus 7, so 166 only shows its first pixel.
+    fn window_starts(&self) -> bool {
+        !self.fifo.window
+            && self.fifo.wy_hit
+            && self.window_display_enabled()
+            && self.fifo.lx + 7 >= self.windowx
+    }
+
+    fn fetcher_dot(&mut self) {
+        let y = if self.fifo.window {
+            self.fifo.window_line as usize
+        } else {
+            self.scanline.wrapping_add(self.scrolly) as usize
+        };
+        if self.fifo.step < FETCH_DOTS {
+            self.fifo.step += 1;
+            match self.fifo.step {
+                2 => {
+                    let (map, x) = if self.fifo.window {
+                        (self.window_tile_map_display_select(), self.fifo.fetch_x)
+                    } else {
+                        (
+                            self.bg_tile_map_display_select(),
+                            self.scrollx / 8 + self.fifo.fetch_x,
+                        )
+                    };
+                    let map = map.start() - VRAM_START;
+                    self.fifo.tile = self.vram[map + y / 8 * 32 + x as usize % 32];
+                }
+                4 => self.fifo.low = self.vram[self.bg_tile_data(self.fifo.tile).start + y % 8 * 2],
+                6 => {
+                    self.fifo.high =
+                        self.vram[self.bg_tile_data(self.fifo.tile).start + y % 8 * 2 + 1]
+                }
+                _ => {}
+            }
+        }
+        let fifo = &mut self.fifo;
+        if fifo.step == FETCH_DOTS && fifo.bg.is_empty() {
+            for bit in (0..8).rev() {
+                fifo.bg
+                    .push_back((fifo.high >> bit & 1) << 1 | fifo.low >> bit & 1);
+            }
+            fifo.step = 0;
+            fifo.fetch_x = fifo.fetch_x.wrapping_add(1);
+        }
+    }
+
+    // Mixes the next sprite into the sprite FIFO, where it only shows through
+    // the pixels sprites before it left transparent.
+    fn fetch_sprite(&mut self) {
+        let sprite = self.fifo.sprites[self.fifo.fetched];
+        self.fifo.fetched += 1;
+        let attributes = SpriteAttribute::from(&sprite.flags);
+        let height = self.sprite_height();
+        let mut row = (self.scanline as usize + 16 - sprite.y as usize) % height;
+        if attributes.yflip {
+            row = height - 1 - row;
+        }
+        let pattern = if height == 16 {
+            sprite.pattern & 0xFE
+        } else {
+            sprite.pattern
+        };
+        // Sprites hanging off the left edge lose the columns already gone.
+        let skip = (self.fifo.lx + 8 - sprite.x) as usize;
+        self.fifo.objects.resize(8, SpritePixel::default());
+        for col in skip..8 {
+            let bit = if attributes.xflip { 7 - col } else { col };
+            let index = self.tile_pixel(pattern as usize * TILE_SIZE, row, bit);
+            let slot = &mut self.fifo.objects[col - skip];
+            if slot.index == 0 {
+                *slot = SpritePixel {
+                    index,
+                    obj1: !attributes.obj0,
+                    behind: attributes.above,
+                };
+            }
+        }
+    }
+
+    fn shift_out(&mut self) -> bool {
+        let bg = match self.fifo.bg.pop_front() {
+            Some(bg) => bg,
+            None => return false,
+        };
+        if self.fifo.discard > 0 {
+            self.fifo.discard -= 1;
+            return false;
+        }
+        let sprite = self.fifo.objects.pop_front().unwrap_or_default();
+        let bg = if self.bg_display_enabled() { bg } else { 0 };
+        let colour =
+            if sprite.index != 0 && self.sprite_display_enabled() && !(sprite.behind && bg != 0) {
+                let palette = if sprite.obj1 {
+                    self.obj1pal
+                } else {
+                    self.obj0pal
+                };
+                shade(palette, sprite.index)
+            } else {
+                shade(self.bgrdpal, bg)
+            };
+        self.screen[self.scanline as usize][self.fifo.lx as usize] = pixel(colour);
+        self.fifo.lx += 1;
+        self.fifo.lx as usize == SCREEN_WIDTH
+    }
+}
@@ -1,19 +1,32 @@
-use crate::{cpu, texture::*};
+mod fifo;
+
+use crate::{
+    bus::Device,
+    constants::{WINDOW_HEIGHT, WINDOW_WIDTH},
+    cpu,
+    texture::*,
+};
+use fifo::PixelFifo;
 use std::{
     fmt::Display,
-    ops::{Index, Range, RangeInclusive},
-    time,
+    ops::{Range, RangeInclusive},
 };
 
 pub const VRAM_START: usize = 0x8000;
 pub const VRAM_END: usize = 0x9FFF;
 pub const OAM_START: usize = 0xFE00;
 pub const OAM_END: usize = 0xFE9F;
+pub const LCD_REGISTERS_START: usize = 0xFF40;
+pub const LCD_REGISTERS_END: usize = 0xFF4B;
 pub const TILE_DATA_RANGE: Range<usize> = 0..0x1800;
 pub const MAP_DATA_RANGE: Range<usize> = 0x1800..0x1C00;
 pub const TILE_SIZE: usize = 16;
+pub const SCREEN_WIDTH: usize = WINDOW_WIDTH as usize;
+pub const SCREEN_HEIGHT: usize = WINDOW_HEIGHT as usize;
+// M-cycles the LCD takes to draw a frame, 154 lines of 456 dots.
+pub const FRAME_CYCLES: usize = 154 * 456 / 4;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Copy)]
 enum GpuMode {
     HBlank, // 0
     VBlank, // 1
@@ -38,12 +51,20 @@ pub struct GPU {
     pub lcdstat: u8,
     pub scrollx: u8,
     pub scrolly: u8,
+    pub lyc: u8,
     pub bgrdpal: u8, //Background Palette
     pub obj0pal: u8, //Object0 Palette
     pub obj1pal: u8, //Object1 Palette
     pub windowx: u8, //
     pub windowy: u8, //
-    pub _vblank_count: usize,
+    pub vblank_count: usize,
+    // What the LCD shows, filled in a pixel at a time during mode 3.
+    pub screen: Box<ScreenData>,
+    frame_ready: bool,
+    // The OR of every STAT interrupt source that's enabled. LCDSTAT is only
+    // requested when it goes high, so overlapping sources block each other.
+    stat_line: bool,
+    fifo: PixelFifo,
 }
 
 const END_HBLANK: u8 = 144;
@@ -51,9 +72,10 @@ const END_VBLANK: u8 = 154;
 
 pub type PixelData = [[u32; 256]; 256];
 pub type PixelMap = [u8; 256 * 256 * 4];
+pub type ScreenData = [[u32; SCREEN_WIDTH]; SCREEN_HEIGHT];
 
 struct SpriteAttribute {
-    above: bool,
+    above: bool, // BG colours 1-3 are drawn over the sprite
     yflip: bool,
     xflip: bool,
     obj0: bool, //True for OBJ0, OBJ1 otherwise.
@@ -86,15 +108,20 @@ impl GPU {
             lcdstat: 0,
             scrolly: 0,
             scrollx: 0,
+            lyc: 0,
             bgrdpal: 0,
             obj0pal: 0,
             obj1pal: 0,
             windowx: 0,
             windowy: 0,
             // FFxx Values end
-            _vblank_count: 0,
+            vblank_count: 0,
             vram: [0; 0x2000],
             oam: [0; 0x100],
+            screen: Box::new([[0; SCREEN_WIDTH]; SCREEN_HEIGHT]),
+            frame_ready: false,
+            stat_line: false,
+            fifo: PixelFifo::default(),
         }
     }
     //   Bit 7 - LCD Display Enable             (0=Off, 1=On)
@@ -117,7 +144,7 @@ impl GPU {
 
     //   Bit 4 - BG & Window Tile Data Select   (0=8800-97FF, 1=8000-8FFF)
     fn bg_and_window_tile_data_select(&self) -> RangeInclusive<usize> {
-        if self.lcdc & 0b0010_0000 != 0 {
+        if self.lcdc & 0b0001_0000 != 0 {
             (0x8000)..=(0x8FFF)
         } else {
             (0x8800)..=(0x97FF)
@@ -137,7 +164,7 @@ impl GPU {
     }
     //   Bit 3 - BG Tile Map Display Select     (0=9800-9BFF, 1=9C00-9FFF)
     fn bg_tile_map_display_select(&self) -> RangeInclusive<usize> {
-        if self.lcdc & 0b0001_0000 != 0 {
+        if self.lcdc & 0b0000_1000 != 0 {
             0x9C00..=0x9FFF
         } else {
             0x9800..=0x9BFF
@@ -147,9 +174,15 @@ impl GPU {
     //   Bit 2 - OBJ (Sprite) Size              (0=8x8, 1=8x16)
     fn sprite_size(&self) -> SpriteSize {
         if self.lcdc & 0b100 == 0b100 {
-            SpriteSize::Square
-        } else {
             SpriteSize::Tall
+        } else {
+            SpriteSize::Square
+        }
+    }
+    fn sprite_height(&self) -> usize {
+        match self.sprite_size() {
+            SpriteSize::Square => 8,
+            SpriteSize::Tall => 16,
         }
     }
     //   Bit 1 - OBJ (Sprite) Display Enable    (0=Off, 1=On)
@@ -157,6 +190,9 @@ impl GPU {
         self.lcdc & 0b10 == 0b10
     }
     //   Bit 0 - BG Display (for CGB see below) (0=Off, 1=On)
+    fn bg_display_enabled(&self) -> bool {
+        self.lcdc & 0b1 == 0b1
+    }
 
     pub fn print_sprite_table(&self) {
         for i in self.oam.chunks_exact(4) {
@@ -169,8 +205,15 @@ impl GPU {
         if !self.is_on() {
             return;
         }
-        self.clock += 1;
-        self.step(flag)
+        // Four dots to an M-cycle.
+        for _ in 0..4 {
+            self.step(flag);
+        }
+    }
+
+    // True once per frame, when the last visible line has been drawn.
+    pub fn take_frame(&mut self) -> bool {
+        std::mem::take(&mut self.frame_ready)
     }
 
     pub fn scroll(&self) -> (u32, u32) {
@@ -204,8 +247,8 @@ impl GPU {
         }
     }
 
-    pub fn render(&self, pixels: &mut PixelData) {
-        let _start = time::Instant::now();
+    // Draws the whole background map, for the map viewer.
+    pub fn render_map(&self, pixels: &mut PixelData) {
         for i in MAP_DATA_RANGE {
             self.blit_tile(pixels, i);
         }
@@ -240,39 +283,66 @@ impl GPU {
         }
     }
 
-    fn check_clock<F: FnOnce(&mut Self)>(&mut self, criteria: usize, f: F) {
-        if self.clock >= criteria {
-            f(self);
+    // Colour index of a pixel of the tile at `start` in VRAM.
+    fn tile_pixel(&self, start: usize, row: usize, col: usize) -> u8 {
+        let lo = self.vram[start + row * 2] >> (7 - col) & 1;
+        let hi = self.vram[start + row * 2 + 1] >> (7 - col) & 1;
+        hi << 1 | lo
+    }
+
+    fn set_mode(&mut self, mode: GpuMode) {
+        self.mode = mode;
+        self.lcdstat = (self.lcdstat & !0b11) | mode as u8;
+    }
+
+    // Runs a single dot, `clock` being the dot within the current line.
+    pub fn step(&mut self, flag: &mut u8) {
+        if matches!(self.mode, GpuMode::OAM) && self.clock == 80 {
+            self.start_pixel_transfer();
+            self.set_mode(GpuMode::VRAM);
+        }
+        if matches!(self.mode, GpuMode::VRAM) && self.pixel_transfer_dot() {
+            self.set_mode(GpuMode::HBlank);
+        }
+        self.clock += 1;
+        if self.clock == 456 {
             self.clock = 0;
+            self.next_line(flag);
         }
+        self.update_stat(flag);
     }
 
-    // This is a huge can of worms to correct emulate the state of the scanline during emulation.
-    // I would revisit this later.
-    pub fn step(&mut self, flag: &mut u8) {
-        match self.mode {
-            GpuMode::OAM => self.check_clock(80, |gpu| gpu.mode = GpuMode::VRAM),
-            GpuMode::VRAM => self.check_clock(172, |gpu| gpu.mode = GpuMode::HBlank),
-            GpuMode::HBlank => self.check_clock(204, |gpu| {
-                gpu.scanline += 1;
-                if gpu.scanline == END_HBLANK {
-                    gpu._vblank_count += 1;
-                    *flag |= cpu::VBLANK;
-                    gpu.mode = GpuMode::VBlank;
-                } else {
-                    gpu.mode = GpuMode::OAM;
-                }
-            }),
-            GpuMode::VBlank => self.check_clock(456, |gpu| {
-                gpu.scanline += 1;
-                if gpu.scanline == END_VBLANK {
-                    gpu.mode = GpuMode::OAM;
-                    gpu.scanline = 0;
-                }
-            }),
+    fn next_line(&mut self, flag: &mut u8) {
+        self.scanline += 1;
+        if self.scanline == END_HBLANK {
+            self.vblank_count += 1;
+            self.frame_ready = true;
+            *flag |= cpu::VBLANK;
+            self.set_mode(GpuMode::VBlank);
+        } else if self.scanline == END_VBLANK {
+            self.scanline = 0;
+            self.set_mode(GpuMode::OAM);
+        } else if self.scanline < END_HBLANK {
+            self.set_mode(GpuMode::OAM);
         }
     }
 
+    // Sets the coincidence bit and requests LCDSTAT if the line has gone high.
+    fn update_stat(&mut self, flag: &mut u8) {
+        let coincidence = self.scanline == self.lyc;
+        self.lcdstat = (self.lcdstat & !0b100) | (coincidence as u8) << 2;
+        let line = match self.mode {
+            GpuMode::HBlank => self.lcdstat & 0b0000_1000 != 0,
+            GpuMode::VBlank => self.lcdstat & 0b0001_0000 != 0,
+            GpuMode::OAM => self.lcdstat & 0b0010_0000 != 0,
+            GpuMode::VRAM => false,
+        } || coincidence && self.lcdstat & 0b0100_0000 != 0;
+        if line && !self.stat_line {
+            *flag |= cpu::LCDSTAT;
+        }
+        self.stat_line = line;
+    }
+
     pub fn hex_dump(&self) {
         let mut start = VRAM_START;
         for row in self.vram.chunks_exact(4) {
@@ -285,14 +355,51 @@ impl GPU {
     }
 }
 
-impl Index<u16> for GPU {
-    type Output = u8;
-    fn index(&self, i: u16) -> &Self::Output {
-        match i {
-            0x44 => &self.scanline,
-            _ => &self.vram[i as usize - 0x8000],
+impl Device for GPU {
+    fn read(&self, address: u16) -> u8 {
+        match address as usize {
+            VRAM_START..=VRAM_END => self.vram[address as usize - VRAM_START],
+            OAM_START..=OAM_END => self.oam[address as usize - OAM_START],
+            0xFF40 => self.lcdc,
+            0xFF41 => self.lcdstat,
+            0xFF42 => self.scrolly,
+            0xFF43 => self.scrollx,
+            0xFF44 => self.scanline,
+            0xFF45 => self.lyc,
+            0xFF47 => self.bgrdpal,
+            0xFF48 => self.obj0pal,
+            0xFF49 => self.obj1pal,
+            0xFF4A => self.windowy,
+            0xFF4B => self.windowx,
+            _ => 0xFF,
         }
     }
+
+    fn write(&mut self, address: u16, value: u8, int_flags: &mut u8) {
+        match address as usize {
+            VRAM_START..=VRAM_END => self.vram[address as usize - VRAM_START] = value,
+            OAM_START..=OAM_END => self.oam[address as usize - OAM_START] = value,
+            0xFF40 => self.lcdc = value,
+            // The mode and coincidence bits are read only.
+            0xFF41 => self.lcdstat = (self.lcdstat & 0b111) | (value & 0b0111_1000),
+            0xFF42 => self.scrolly = value,
+            0xFF43 => self.scrollx = value,
+            0xFF45 => self.lyc = value,
+            0xFF47 => self.bgrdpal = value,
+            0xFF48 => self.obj0pal = value,
+            0xFF49 => self.obj1pal = value,
+            0xFF4A => self.windowy = value,
+            0xFF4B => self.windowx = value,
+            _ => {}
+        }
+        if self.is_on() && matches!(address, 0xFF41 | 0xFF45) {
+            self.update_stat(int_flags);
+        }
+    }
+
+    fn tick(&mut self, int_flags: &mut u8) {
+        self.cycle(int_flags);
+    }
 }
 
 impl Display for GPU {
@@ -311,7 +418,7 @@ BG+Window Tile Data Select: {:04X}-{:04X}
 BG Tile Map Display Select: {:04X}-{:04X}
 Sprite Size: {:?} 
 Sprite Display Enable: {} 
-BG Display: UNIMPLEMENTED
+BG Display: {}
 STAT: {:08b}"#,
             self.lcdc,
             self.is_on(),
@@ -324,8 +431,11 @@ STAT: {:08b}"#,
             bgtmds.end(),
             self.sprite_size(),
             self.sprite_display_enabled(),
-            // self.bg_display_enabled(),
+            self.bg_display_enabled(),
             self.lcdstat,
         ))
     }
 }
+
+#[cfg(test)]
+mod test;
@@ -0,0 +1,320 @@
+use super::*;
+
+// LCD on with the background and sprites, tiles at 8000 and the map at 9800.
+// Every map entry is tile 1, whose left half is colour 1 and right half colour 0.
+fn striped() -> GPU {
+    let mut gpu = GPU::new();
+    gpu.lcdc = 0b1001_0011;
+    gpu.bgrdpal = 0b1110_0100;
+    gpu.obj0pal = 0b1110_0100;
+    for row in 0..8 {
+        gpu.vram[TILE_SIZE + row * 2] = 0xF0;
+    }
+    for entry in &mut gpu.vram[MAP_DATA_RANGE] {
+        *entry = 1;
+    }
+    gpu
+}
+
+fn run_lines(gpu: &mut GPU, lines: usize) {
+    for _ in 0..lines * 456 / 4 {
+        gpu.cycle(&mut 0);
+    }
+}
+
+// Runs a whole line a dot at a time, returning how long mode 3 was.
+fn mode3_dots(gpu: &mut GPU) -> usize {
+    let line = gpu.scanline;
+    let mut dots = 0;
+    while gpu.scanline == line {
+        gpu.step(&mut 0);
+        if gpu.read(0xFF41) & 0b11 == 3 {
+            dots += 1;
+        }
+    }
+    // The dot the last pixel goes out in already reads as HBlank.
+    dots + 1
+}
+
+fn sprite_at(gpu: &mut GPU, slot: usize, x: u8) {
+    gpu.oam[slot * 4..slot * 4 + 4].copy_from_slice(&[16, x, 2, 0]);
+}
+
+#[test]
+fn lines_use_current_registers() {
+    let mut gpu = striped();
+    run_lines(&mut gpu, 1);
+    gpu.scrollx = 4;
+    run_lines(&mut gpu, 1);
+    assert_eq!(gpu.screen[0][0], pixel(1));
+    assert_eq!(gpu.screen[0][4], pixel(0));
+    assert_eq!(gpu.screen[1][0], pixel(0));
+    assert_eq!(gpu.screen[1][4], pixel(1));
+}
+
+#[test]
+fn frame_ready_at_vblank() {
+    let mut gpu = striped();
+    let mut flags = 0;
+    for _ in 0..144 * 456 / 4 - 1 {
+        gpu.cycle(&mut flags);
+    }
+    assert!(!gpu.take_frame());
+    gpu.cycle(&mut flags);
+    assert_eq!(gpu.scanline, 144);
+    assert_eq!(flags, cpu::VBLANK);
+    assert!(gpu.take_frame());
+    assert!(!gpu.take_frame());
+    run_lines(&mut gpu, 154);
+    assert!(gpu.take_frame());
+}
+
+#[test]
+fn sprite_priority() {
+    let mut gpu = striped();
+    // Tile 2 is solid colour 3.
+    for byte in &mut gpu.vram[2 * TILE_SIZE..3 * TILE_SIZE] {
+        *byte = 0xFF;
+    }
+    // Sprite 0 covers x 6..14 behind the background, sprite 1 x 2..10 in front.
+    gpu.oam[..8].copy_from_slice(&[16, 14, 2, 0x80, 16, 10, 2, 0]);
+    run_lines(&mut gpu, 1);
+    let line = &gpu.screen[0];
+    assert_eq!(line[1], pixel(1));
+    assert_eq!(line[2..10], [pixel(3); 8]);
+    // Sprite 0 is behind colour 1 but shows over colour 0.
+    assert_eq!(line[10..12], [pixel(1); 2]);
+    assert_eq!(line[12..14], [pixel(3); 2]);
+    assert_eq!(line[14], pixel(0));
+
+    // Sprite 0 moves to x 0..8, further left, and wins even where it's hidden.
+    gpu.oam[1] = 8;
+    run_lines(&mut gpu, 154);
+    let line = &gpu.screen[0];
+    assert_eq!(line[..4], [pixel(1); 4]);
+    assert_eq!(line[4..10], [pixel(3); 6]);
+    assert_eq!(line[10], pixel(1));
+}
+
+#[test]
+fn mode3_length() {
+    let mut gpu = striped();
+    assert_eq!(mode3_dots(&mut gpu), 172);
+    assert_eq!(gpu.read(0xFF41) & 0b11, 2);
+    // Pixels discarded for the fine scroll.
+    gpu.scrollx = 3;
+    assert_eq!(mode3_dots(&mut gpu), 175);
+    gpu.scrollx = 8;
+    assert_eq!(mode3_dots(&mut gpu), 172);
+}
+
+#[test]
+fn sprite_penalties() {
+    // (sprite X, extra dots), the wait for the background fetch shrinks the
+    // further into a tile the sprite starts.
+    for &(x, penalty) in &[(24, 11), (26, 9), (29, 6), (31, 6), (0, 11), (168, 0)] {
+        let mut gpu = striped();
+        sprite_at(&mut gpu, 0, x);
+        assert_eq!(mode3_dots(&mut gpu), 172 + penalty, "sprite at {}", x);
+    }
+    // Sprites on the same spot pay for the second fetch only.
+    let mut gpu = striped();
+    sprite_at(&mut gpu, 0, 24);
+    sprite_at(&mut gpu, 1, 24);
+    assert_eq!(mode3_dots(&mut gpu), 172 + 11 + 6);
+    // Only the first 10 sprites on the line are fetched.
+    for slot in 0..12 {
+        sprite_at(&mut gpu, slot, 31);
+    }
+    assert_eq!(mode3_dots(&mut gpu), 172 + 60);
+    // Nor are they with sprites off.
+    gpu.lcdc &= !0b10;
+    assert_eq!(mode3_dots(&mut gpu), 172);
+}
+
+#[test]
+fn sprites_stay_on_their_pixels() {
+    let mut gpu = striped();
+    for byte in &mut gpu.vram[2 * TILE_SIZE..3 * TILE_SIZE] {
+        *byte = 0xFF;
+    }
+    gpu.scrollx = 5;
+    sprite_at(&mut gpu, 0, 4);
+    sprite_at(&mut gpu, 1, 100);
+    run_lines(&mut gpu, 1);
+    let line = &gpu.screen[0];
+    assert_eq!(line[..4], [pixel(3); 4]);
+    // With 5 pixels scrolled off, the second tile starts at 3.
+    assert_eq!(line[4..7], [pixel(1); 3]);
+    assert_eq!(line[7..11], [pixel(0); 4]);
+    assert_eq!(line[91], pixel(1));
+    assert_eq!(line[92..100], [pixel(3); 8]);
+    assert_eq!(line[100], pixel(1));
+}
+
+// The window map at 9C00 is all tile 2, which is colour 3 on its first row,
+// colour 2 on its second and colour 0 after that.
+fn windowed(wx: u8, wy: u8) -> GPU {
+    let mut gpu = striped();
+    gpu.lcdc |= 0b0110_0000;
+    gpu.windowx = wx;
+    gpu.windowy = wy;
+    gpu.vram[2 * TILE_SIZE..2 * TILE_SIZE + 4].copy_from_slice(&[0xFF, 0xFF, 0x00, 0xFF]);
+    for entry in &mut gpu.vram[0x1C00..0x2000] {
+        *entry = 2;
+    }
+    gpu
+}
+
+#[test]
+fn window_position() {
+    let mut gpu = windowed(87, 1);
+    assert_eq!(mode3_dots(&mut gpu), 172);
+    // The fetcher restarting for the window costs a fetch.
+    assert_eq!(mode3_dots(&mut gpu), 172 + 6);
+    assert_eq!(gpu.screen[0][80], pixel(1));
+    assert_eq!(gpu.screen[1][79], pixel(0));
+    assert_eq!(gpu.screen[1][80..], [pixel(3); 80][..]);
+    run_lines(&mut gpu, 1);
+    assert_eq!(gpu.screen[2][80..], [pixel(2); 80][..]);
+}
+
+#[test]
+fn window_edges() {
+    // WX 166 leaves just the last pixel, 167 is off screen.
+    let mut gpu = windowed(166, 0);
+    run_lines(&mut gpu, 1);
+    assert_eq!(gpu.screen[0][158], pixel(0));
+    assert_eq!(gpu.screen[0][159], pixel(3));
+    gpu.windowx = 167;
+    run_lines(&mut gpu, 1);
+    assert_eq!(gpu.screen[1][159], pixel(0));
+    // Under 7 the window's left edge is cut off, rather than starting later.
+    gpu.windowx = 3;
+    gpu.vram[2 * TILE_SIZE..2 * TILE_SIZE + 2].copy_from_slice(&[0x0F, 0x0F]);
+    run_lines(&mut gpu, 153);
+    assert_eq!(gpu.screen[0][..4], [pixel(3); 4]);
+    assert_eq!(gpu.screen[0][4..8], [pixel(0); 4]);
+    assert_eq!(gpu.screen[0][8..12], [pixel(3); 4]);
+}
+
+#[test]
+fn window_line_counter() {
+    let mut gpu = windowed(7, 0);
+    run_lines(&mut gpu, 1);
+    assert_eq!(gpu.screen[0][0], pixel(3));
+    // Lines without the window, turned off or pushed off screen, don't count.
+    gpu.lcdc &= !0b0010_0000;
+    run_lines(&mut gpu, 1);
+    gpu.lcdc |= 0b0010_0000;
+    gpu.windowx = 200;
+    run_lines(&mut gpu, 1);
+    gpu.windowx = 7;
+    run_lines(&mut gpu, 1);
+    assert_eq!(gpu.screen[1][0], pixel(1));
+    assert_eq!(gpu.screen[2][0], pixel(1));
+    assert_eq!(gpu.screen[3][0], pixel(2));
+    // It starts over with the frame.
+    run_lines(&mut gpu, 151);
+    assert_eq!(gpu.screen[0][0], pixel(3));
+}
+
+// How many times LCDSTAT is requested over the next line.
+fn stat_requests(gpu: &mut GPU) -> usize {
+    let line = gpu.scanline;
+    let mut requests = 0;
+    while gpu.scanline == line {
+        let mut flags = 0;
+        gpu.step(&mut flags);
+        requests += (flags & cpu::LCDSTAT != 0) as usize;
+    }
+    requests
+}
+
+#[test]
+fn lyc_coincidence() {
+    let mut gpu = striped();
+    gpu.write(0xFF45, 2, &mut 0);
+    run_lines(&mut gpu, 2);
+    assert_eq!(gpu.read(0xFF41) & 0b100, 0b100);
+    // Writes leave it alone.
+    gpu.write(0xFF41, 0, &mut 0);
+    assert_eq!(gpu.read(0xFF41) & 0b100, 0b100);
+    run_lines(&mut gpu, 1);
+    assert_eq!(gpu.read(0xFF41) & 0b100, 0);
+
+    // Moving LYC onto LY requests LCDSTAT straight away.
+    let mut flags = 0;
+    gpu.write(0xFF41, 0b0100_0000, &mut flags);
+    assert_eq!(flags, 0);
+    gpu.write(0xFF45, 3, &mut flags);
+    assert_eq!(flags, cpu::LCDSTAT);
+}
+
+#[test]
+fn stat_interrupts() {
+    let mut gpu = striped();
+    run_lines(&mut gpu, 1);
+    assert_eq!(stat_requests(&mut gpu), 0);
+    for &source in &[0b0000_1000, 0b0010_0000, 0b0100_0000] {
+        gpu.write(0xFF41, source, &mut 0);
+        gpu.lyc = gpu.scanline;
+        assert_eq!(stat_requests(&mut gpu), 1, "{:08b}", source);
+    }
+    // HBlank runs straight into the next line's OAM scan, so with both
+    // enabled the line never drops and OAM is blocked.
+    gpu.write(0xFF41, 0b0010_1000, &mut 0);
+    assert_eq!(stat_requests(&mut gpu), 1);
+    // LYC matching the next line keeps it high through that line's HBlank too.
+    gpu.write(0xFF41, 0b0100_1000, &mut 0);
+    gpu.lyc = gpu.scanline + 1;
+    assert_eq!(stat_requests(&mut gpu), 1);
+    assert_eq!(stat_requests(&mut gpu), 0);
+
+    gpu.write(0xFF41, 0b0001_0000, &mut 0);
+    let mut flags = 0;
+    while gpu.scanline != 144 {
+        flags = 0;
+        gpu.step(&mut flags);
+    }
+    assert_eq!(flags, cpu::VBLANK | cpu::LCDSTAT);
+}
+
+// dmg-acid2 signals with LD B,B once it has set up its final frame, which is
+// then redrawn every frame after.
+#[test]
+#[ignore = "dmg-acid2 isn't checked in, copy dmg-acid2.gb and reference-dmg.png to test_roms/dmg-acid2/"]
+fn dmg_acid2() {
+    let rom = std::fs::read("test_roms/dmg-acid2/dmg-acid2.gb").expect("dmg-acid2.gb");
+    let reference =
+        std::fs::File::open("test_roms/dmg-acid2/reference-dmg.png").expect("reference-dmg.png");
+    let mut decoder = png::Decoder::new(reference);
+    decoder.set_transformations(png::Transformations::EXPAND);
+    let (info, mut reader) = decoder.read_info().unwrap();
+    let mut reference = vec![0; info.buffer_size()];
+    reader.next_frame(&mut reference).unwrap();
+    let channels = reference.len() / (SCREEN_WIDTH * SCREEN_HEIGHT);
+
+    let mut emu = crate::emu::Emu::new(rom, None);
+    while emu.cpu.opcode != 0x40 {
+        emu.emulate_step();
+        assert!(emu.bus.clock < 10_000_000, "dmg-acid2 never finished");
+    }
+    emu.run_frame();
+    emu.run_frame();
+    let screen = &emu.bus.gpu.screen;
+    let wrong: Vec<_> = (0..SCREEN_HEIGHT)
+        .flat_map(|y| (0..SCREEN_WIDTH).map(move |x| (x, y)))
+        .filter(|&(x, y)| {
+            // White is 0xFF, then 0xAA, 0x55 and black.
+            let grey = reference[(y * SCREEN_WIDTH + x) * channels];
+            screen[y][x] != pixel(3 - grey / 0x55)
+        })
+        .collect();
+    assert!(
+        wrong.is_empty(),
+        "{} pixels differ, first at {:?}",
+        wrong.len(),
+        wrong[0]
+    );
+}
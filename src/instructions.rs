@@ -1,11 +1,13 @@
 use self::Flag::*;
+use self::CBInstr::*;
 use self::Instr::*;
 use self::Location::*;
 use self::Register::*;
-use crate::{
-    bus::Bus,
-    cpu::{value::Value, CPU},
-};
+use std::fmt::Display;
+
+use self::Location16::*;
+use self::Register16::*;
+use crate::{bus::Bus, cpu::CPU};
 
 
 #[derive(Debug, PartialEq, Copy, Clone)]
@@ -15,14 +17,16 @@ pub enum Register {
     C,
     D,
     E,
-    F, //FLAGS
     H,
     L,
-    SP,
-    PC,
+}
+
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Register16 {
     BC,
     DE,
     HL,
+    SP,
     AF,
 }
 
@@ -34,15 +38,23 @@ pub enum Flag {
     FlagNC,
 }
 
+// Where an 8-bit operand is read from or written to.
 #[derive(Debug, PartialEq, Copy, Clone)]
 pub enum Location {
-    Memory(Register),
-    Immediate(usize), // Bytes
+    Memory(Register16),
+    Immediate,
     Register(Register),
     MemOffsetImm,
     MemoryImmediate,
     MemOffsetC,
-    Literal(Value),
+}
+
+// Where a 16-bit operand is read from or written to.
+#[derive(Debug, PartialEq, Copy, Clone)]
+pub enum Location16 {
+    Pair(Register16),
+    Immediate16,
+    MemoryImmediate16,
 }
 
 #[derive(Debug, PartialEq, Copy, Clone)]
@@ -53,355 +65,135 @@ pub enum Direction {
 
 type Condition = Option<Flag>;
 
+// Returns None when the instruction isn't done yet, see `CPU::bus_cycle`.
 pub trait Executable {
-    fn execute(self, cpu: &mut CPU, bus: &mut Bus);
+    fn execute(self, cpu: &mut CPU, bus: &mut Bus) -> Option<()>;
 }
 
-impl Register {
-    pub fn is_dual_register(self) -> bool {
+impl Location {
+    // Number of operand bytes this location reads from the instruction stream.
+    pub fn data_length(self) -> usize {
         match self {
-            HL => true,
-            BC => true,
-            DE => true,
-            SP => true,
-            _ => false,
+            Immediate | MemOffsetImm => 1,
+            MemoryImmediate => 2,
+            _ => 0,
         }
     }
 }
 
-impl Location {
-    pub fn is_dual_register(self) -> bool {
-        if let Register(r) = self {
-            r.is_dual_register()
-        } else {
-            false
+impl Location16 {
+    pub fn data_length(self) -> usize {
+        match self {
+            Pair(_) => 0,
+            Immediate16 | MemoryImmediate16 => 2,
+        }
+    }
+}
+
+impl Executable for Instr {
+    fn execute(self, cpu: &mut CPU, bus: &mut Bus) -> Option<()> {
+        match self {
+            NOOP => cpu.noop(bus),
+            UNIMPLEMENTED => cpu.lock(bus),
+            LD16(Pair(SP), Pair(HL)) => {
+                cpu.internal_cycle(bus)?;
+                cpu.ld16(Pair(SP), Pair(HL), bus)
+            }
+            LD(into, from) => cpu.ld(into, from, bus),
+            LD16(into, from) => cpu.ld16(into, from, bus),
+            LDD(into, from) => cpu.ldd(into, from, bus),
+            LDI(into, from) => cpu.ldi(into, from, bus),
+            LDSP => cpu.ldsp(bus),
+            INC(l) => cpu.inc(l, bus),
+            DEC(l) => cpu.dec(l, bus),
+            INC16(r) => cpu.inc16(r, bus),
+            DEC16(r) => cpu.dec16(r, bus),
+            ADD(l) => cpu.add(l, bus),
+            ADDHL(r) => cpu.addhl(r, bus),
+            ADC(l) => cpu.adc(l, bus),
+            SUB(l) => cpu.sub(l, bus),
+            AND(l) => cpu.and(l, bus),
+            XOR(l) => cpu.xor(l, bus),
+            OR(l) => cpu.orr(l, bus),
+            CP(l) => cpu.cp(l, bus),
+            SBC(l) => cpu.sbc(l, bus),
+            CB => cpu.handle_cb(bus),
+            JR(condition) => cpu.jr(condition, bus),
+            STOP => cpu.stop(bus),
+            DisableInterrupts => cpu.disableinterrupts(bus),
+            EnableInterrupts => cpu.enableinterrupts(bus),
+            JP(condition) => cpu.jp(condition, bus),
+            JpHl => cpu.jp_hl(bus),
+            RET(condition) => cpu.ret(condition, bus),
+            RETI => cpu.reti(bus),
+            DAA => cpu.daa(bus),
+            POP(r) => cpu.pop(r, bus),
+            PUSH(r) => cpu.push(r, bus),
+            NOT(l) => cpu.not(l, bus),
+            CALL(condition) => cpu.call(condition, bus),
+            RLCA => cpu.rlca(bus),
+            RRCA => cpu.rrca(bus),
+            RLA => cpu.rla(bus),
+            RRA => cpu.rra(bus),
+            SCF => cpu.scf(bus),
+            CCF => cpu.ccf(bus),
+            ADDSP => cpu.addsp(bus),
+            HALT => cpu.halt(bus),
+            RST(size) => cpu.rst(size as u16, bus),
+        }
+    }
+}
+
+impl Executable for CBInstr {
+    fn execute(self, cpu: &mut CPU, bus: &mut Bus) -> Option<()> {
+        match self {
+            RLC(l) => cpu.rlc(l, bus),
+            RRC(l) => cpu.rrc(l, bus),
+            RL(l) => cpu.rl(l, bus),
+            RR(l) => cpu.rr(l, bus),
+            SLA(l) => cpu.sla(l, bus),
+            SRA(l) => cpu.sra(l, bus),
+            SWAP(l) => cpu.swap(l, bus),
+            SRL(l) => cpu.srl(l, bus),
+            BIT(bit, l) => cpu.bit(bit, l, bus),
+            RES(bit, l) => cpu.res(bit, l, bus),
+            SET(bit, l) => cpu.set(bit, l, bus),
         }
     }
 }
 
-// impl Executable for Instr {
-//     fn execute(self, cpu: &mut CPU, bus: &mut Bus) {
-//         // println!("{} {:?}", cpu.registers.pc, cpu.opcode);
-//         match self {
-//             LD(Register(SP), Register(HL)) => {
-//                 cpu.registers.sp = cpu.registers.hl();
-//                 bus.generic_cycle();
-//             }
-//             LD(into, from) => cpu.load(into, from, bus),
-//             LDD(into, from) => {
-//                 cpu.load(into, from, bus);
-//                 cpu.registers.dec_reg(Register::HL);
-//             }
-//             LDI(into, from) => {
-//                 cpu.load(into, from, bus);
-//                 cpu.registers.inc_reg(Register::HL);
-//             }
-//             LDSP => {
-//                 let offset = cpu.next_u8(bus) as i8 as u16;
-//                 let result = cpu.registers.sp.wrapping_add(offset); // todo ?
-//                 let half_carry = (cpu.registers.sp & 0x0F).wrapping_add(offset & 0x0F) > 0x0F;
-//                 let carry = (cpu.registers.sp & 0xFF).wrapping_add(offset & 0xFF) > 0xFF;
-//                 cpu.write_into(Location::Register(HL), U16(result), bus);
-//                 bus.generic_cycle();
-//                 cpu.registers.set_zf(false);
-//                 cpu.registers.set_nf(false);
-//                 cpu.registers.set_hf(half_carry);
-//                 cpu.registers.set_cf(carry);
-//             }
-//             STOP => {
-//                 // println!("STOP: {:04x}", cpu.registers.pc - 1); // TODO ?
-//             }
-//             NOOP => (),
-//             RST(size) => {
-//                 bus.generic_cycle();
-//                 cpu.push_stack(cpu.registers.pc, bus);
-//                 cpu.registers.pc = size as u16;
-//             }
-//             CP(location) => {
-//                 let value = cpu.read_from(location, bus).into();
-//                 cpu.registers.set_zf(cpu.registers.a == value);
-//                 cpu.registers.set_nf(true);
-//                 //https://github.com/Gekkio/mooneye-gb/blob/ca7ff30b52fd3de4f1527397f27a729ffd848dfa/core/src/cpu.rs#L156
-//                 cpu.registers
-//                     .set_hf((cpu.registers.a & 0xf).wrapping_sub(value & 0xf) & (0xf + 1) != 0);
-//                 cpu.registers.set_cf(cpu.registers.a < value);
-//             }
-//             ADD(location) => {
-//                 let value = cpu.read_from(location, bus).into();
-//                 let (result, carry) = cpu.registers.a.overflowing_add(value);
-//                 //https://github.com/Gekkio/mooneye-gb/blob/ca7ff30b52fd3de4f1527397f27a729ffd848dfa/core/src/cpu/execute.rs#L55
-//                 let half_carry = (cpu.registers.a & 0x0f).checked_add(value | 0xf0).is_none();
-//                 cpu.registers.a = result;
-//                 cpu.registers.set_zf(cpu.registers.a == 0);
-//                 cpu.registers.set_nf(false);
-//                 cpu.registers.set_hf(half_carry);
-//                 cpu.registers.set_cf(carry);
-//             }
-//             SUB(location) => {
-//                 let value = cpu.read_from(location, bus).into();
-//                 let result = cpu.registers.a.wrapping_sub(value);
-//                 cpu.registers.set_zf(result == 0);
-//                 cpu.registers.set_nf(true);
-//                 cpu.registers.set_hf(
-//                     // Mooneye
-//                     (cpu.registers.a & 0xf).wrapping_sub(value & 0xf) & (0xf + 1) != 0,
-//                 );
-//                 cpu.registers
-//                     .set_cf((cpu.registers.a as u16) < (value as u16));
-//                 cpu.registers.a = result;
-//             }
-//             ADC(location) => {
-//                 let value = cpu.read_from(location, bus).into();
-//                 let carry = cpu.registers.flg_c() as u8;
-//                 let result = cpu.registers.a.wrapping_add(value).wrapping_add(carry);
-//                 cpu.registers.set_zf(result == 0);
-//                 cpu.registers.set_nf(false);
-//                 // Maybe: See https://github.com/Gekkio/mooneye-gb/blob/ca7ff30b52fd3de4f1527397f27a729ffd848dfa/core/src/cpu/execute.rs#L55
-//                 cpu.registers
-//                     .set_hf((cpu.registers.a & 0xf) + (value & 0xf) + carry > 0xf);
-//                 cpu.registers
-//                     .set_cf(cpu.registers.a as u16 + value as u16 + carry as u16 > 0xff);
-//                 cpu.registers.a = result;
-//             }
-//             ADDHL(location) => {
-//                 let hl = cpu.registers.hl();
-//                 if let U16(value) = cpu.read_from(location, bus) {
-//                     if location.is_dual_register() {
-//                         bus.generic_cycle();
-//                     }
-//                     let (result, overflow) = hl.overflowing_add(value);
-//                     let [h, l] = result.to_be_bytes();
-//                     cpu.registers.h = h;
-//                     cpu.registers.l = l;
-//                     cpu.registers.set_nf(false);
-//                     cpu.registers
-//                         .set_hf((hl & 0xfff) + (value & 0xfff) > 0x0fff);
-//                     cpu.registers.set_cf(overflow);
-//                 } else {
-//                     unimplemented!()
-//                 }
-//             }
-//             AND(location) => {
-//                 let value: u8 = cpu.read_from(location, bus).into();
-//                 cpu.registers.a &= value;
-//                 cpu.registers.set_zf(cpu.registers.a == 0);
-//                 cpu.registers.set_nf(false);
-//                 cpu.registers.set_hf(true);
-//                 cpu.registers.set_cf(false);
-//             }
-//             XOR(location) => {
-//                 let value: u8 = cpu.read_from(location, bus).into();
-//                 cpu.registers.a ^= value;
-//                 cpu.registers.set_zf(cpu.registers.a == 0);
-//                 cpu.registers.set_nf(false);
-//                 cpu.registers.set_hf(false);
-//                 cpu.registers.set_cf(false);
-//             }
-//             OR(location) => {
-//                 let value: u8 = cpu.read_from(location, bus).into();
-//                 cpu.registers.a |= value;
-//                 cpu.registers.set_zf(cpu.registers.a == 0);
-//                 cpu.registers.set_nf(false);
-//                 cpu.registers.set_hf(false);
-//                 cpu.registers.set_cf(false);
-//             }
-//             NOT(location) => {
-//                 let value: u8 = cpu.read_from(location, bus).into();
-//                 cpu.registers.a = !value;
-//                 cpu.registers.set_nf(true);
-//                 cpu.registers.set_hf(true);
-//             }
-//             CCF => {
-//                 cpu.registers.set_nf(false);
-//                 cpu.registers.set_hf(false);
-//                 cpu.registers.set_cf(!cpu.registers.flg_c());
-//             }
-//             SCF => {
-//                 cpu.registers.set_nf(false);
-//                 cpu.registers.set_hf(false);
-//                 cpu.registers.set_cf(true);
-//             }
-//             HALT => {
-//                 //TODO
-//                 // panic!();
-//                 cpu.halt = true;
-//             }
-//             CB => cpu.handle_cb(bus),
-//             JP(jump_type) => {
-//                 let address = cpu.next_u16(bus);
-//                 cpu.jumping(jump_type, bus, |cpu, _| cpu.registers.pc = address);
-//             }
-//             JP_HL => {
-//                 cpu.registers.pc = cpu.registers.hl();
-//             }
-//             JR(jump_type) => {
-//                 let offset = cpu.next_u8(bus) as i8;
-//                 let address = cpu.registers.pc.wrapping_add(offset as u16);
-//                 cpu.jumping(jump_type, bus, |cpu, _| {
-//                     cpu.registers.pc = address;
-//                 });
-//             }
-//             CALL(jump_type) => {
-//                 let address = cpu.next_u16(bus);
-//                 cpu.jumping(jump_type, bus, |cpu, bus| {
-//                     cpu.push_stack(cpu.registers.pc, bus);
-//                     cpu.registers.pc = address;
-//                 });
-//             }
-//             DEC(Location::Memory(r)) => {
-//                 let address = cpu.registers.fetch_u16(r);
-//                 let value = bus.read_cycle(address);
-//                 let result = value.wrapping_sub(1);
-//                 bus.write_cycle(address, result);
-//                 cpu.registers.set_zf(result == 0);
-//                 cpu.registers.set_nf(true);
-//                 cpu.registers.set_hf(result & 0x0f == 0x0f);
-//             }
-//             DEC(Location::Register(r)) => {
-//                 cpu.registers.dec_reg(r);
-//                 if r.is_dual_register() {
-//                     bus.generic_cycle();
-//                 }
-//             }
-//             INC(Location::Memory(r)) => {
-//                 let address = cpu.registers.fetch_u16(r);
-//                 let value = bus.read_cycle(address);
-//                 let result = value.wrapping_add(1);
-//                 bus.write_cycle(address, result);
-//                 cpu.registers.set_zf(result == 0);
-//                 cpu.registers.set_nf(false);
-//                 cpu.registers.set_hf(value & 0x0f == 0x0f);
-//             }
-//             INC(Location::Register(r)) => {
-//                 cpu.registers.inc(r);
-//                 if r.is_dual_register() {
-//                     bus.generic_cycle();
-//                 }
-//             }
-//             PUSH(Location::Register(r)) => {
-//                 let addr = cpu.registers.fetch_u16(r);
-//                 cpu.push_stack(addr, bus);
-//                 bus.generic_cycle();
-//             }
-//             POP(Location::Register(r)) => {
-//                 let addr = cpu.pop_stack(bus);
-//                 addr.to_register(&mut cpu.registers, r);
-//             }
-//             RET(None) => cpu.jumping(None, bus, |cpu, bus| {
-//                 cpu.registers.pc = cpu.pop_stack(bus);
-//             }),
-//             RET(jump_type) => {
-//                 cpu.jumping(jump_type, bus, |cpu, bus| {
-//                     cpu.registers.pc = cpu.pop_stack(bus);
-//                 });
-//                 bus.generic_cycle();
-//             }
-//             RRA => {
-//                 let carry = cpu.registers.a & 1 != 0;
-//                 cpu.registers.a >>= 1;
-//                 if cpu.registers.flg_c() {
-//                     cpu.registers.a |= 0b1000_0000;
-//                 }
-//                 cpu.registers.set_zf(false);
-//                 cpu.registers.set_hf(false);
-//                 cpu.registers.set_nf(false);
-//                 cpu.registers.set_cf(carry);
-//             }
-//             RRCA => {
-//                 let carry = cpu.registers.a & 1 != 0;
-//                 cpu.registers.a >>= 1;
-//                 if carry {
-//                     cpu.registers.a |= 0b1000_0000;
-//                 }
-//                 cpu.registers.set_zf(false);
-//                 cpu.registers.set_hf(false);
-//                 cpu.registers.set_nf(false);
-//                 cpu.registers.set_cf(carry);
-//             }
-//             RLA => {
-//                 let overflow = cpu.registers.a & 0x80 != 0;
-//                 let result = cpu.registers.a << 1;
-//                 cpu.registers.a = result | (cpu.registers.flg_c() as u8);
-//                 cpu.registers.set_zf(false);
-//                 cpu.registers.set_hf(false);
-//                 cpu.registers.set_nf(false);
-//                 cpu.registers.set_cf(overflow);
-//             }
-//             RLCA => {
-//                 let carry = cpu.registers.a & 0x80 != 0;
-//                 let result = cpu.registers.a << 1 | carry as u8;
-//                 cpu.registers.a = result;
-//                 cpu.registers.set_zf(false);
-//                 cpu.registers.set_hf(false);
-//                 cpu.registers.set_nf(false);
-//                 cpu.registers.set_cf(carry);
-//             }
-//             ADDSP => {
-//                 let offset = cpu.next_u8(bus) as i8 as i16 as u16;
-//                 let sp = cpu.registers.sp;
-//                 let result = cpu.registers.sp.wrapping_add(offset);
-//                 bus.generic_cycle();
-//                 bus.generic_cycle();
-//                 let half_carry = ((sp & 0x0F) + (offset & 0x0F)) > 0x0F;
-//                 let overflow = ((sp & 0xff) + (offset & 0xff)) > 0xff;
-//                 cpu.registers.sp = result;
-//                 cpu.registers.set_zf(false);
-//                 cpu.registers.set_nf(false);
-//                 cpu.registers.set_hf(half_carry);
-//                 cpu.registers.set_cf(overflow);
-//             }
-//             RETI => {
-//                 bus.enable_interrupts();
-//                 let addr = cpu.pop_stack(bus);
-//                 cpu.registers.pc = addr;
-//                 bus.generic_cycle();
-//             }
-//             DAA => {
-//                 cpu.registers.a = cpu.bcd_adjust(cpu.registers.a);
-//             }
-//             EnableInterrupts => {
-//                 bus.enable_interrupts();
-//             }
-//             DisableInterrupts => {
-//                 bus.disable_interrupts();
-//             }
-//             UNIMPLEMENTED => {},
-//             SBC(l) => {
-//                 let a = cpu.registers.a;
-//                 let value: u8 = cpu.read_from(l, bus).into();
-//                 let cy = cpu.registers.flg_c() as u8;
-//                 let result = a.wrapping_sub(value).wrapping_sub(cy);
-//                 cpu.registers.set_zf(result == 0);
-//                 cpu.registers.set_nf(true);
-//                 cpu.registers.set_hf(
-//                     // Mooneye
-//                     (cpu.registers.a & 0xf)
-//                         .wrapping_sub(value & 0xf)
-//                         .wrapping_sub(cy)
-//                         & (0xf + 1)
-//                         != 0,
-//                 );
-//                 cpu.registers
-//                     .set_cf((cpu.registers.a as u16) < (value as u16) + (cy as u16));
-//                 cpu.registers.a = result;
-//             }
-//             _ => unimplemented!(),
-//         }
-//     }
-// }
+impl Instr {
+    // Number of operand bytes following the opcode.
+    pub fn data_length(self) -> usize {
+        match self {
+            LD(into, from) | LDD(into, from) | LDI(into, from) => {
+                into.data_length() + from.data_length()
+            }
+            LD16(into, from) => into.data_length() + from.data_length(),
+            INC(l) | DEC(l) | ADD(l) | ADC(l) | SUB(l) | AND(l) | XOR(l) | OR(l) | CP(l)
+            | SBC(l) | NOT(l) => l.data_length(),
+            LDSP | ADDSP | JR(_) | STOP | CB => 1,
+            JP(_) | CALL(_) => 2,
+            _ => 0,
+        }
+    }
+}
 
 #[derive(Debug, Copy, Clone, PartialEq)]
 pub enum Instr {
     NOOP,
     UNIMPLEMENTED,
     LD(Location, Location), // (To, From)
+    LD16(Location16, Location16),
     LDD(Location, Location),
     LDI(Location, Location),
     LDSP,
     INC(Location),
     DEC(Location),
+    INC16(Register16),
+    DEC16(Register16),
     ADD(Location),
-    ADDHL(Location),
+    ADDHL(Register16),
     ADC(Location),
     SUB(Location),
     AND(Location),
@@ -419,8 +211,8 @@ pub enum Instr {
     RET(Condition),
     RETI,
     DAA,
-    POP(Location),
-    PUSH(Location),
+    POP(Register16),
+    PUSH(Register16),
     NOT(Location),
     CALL(Condition),
     RLCA,
@@ -440,72 +232,150 @@ impl Default for Instr {
     }
 }
 
+// Instructions following the 0xCB prefix.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum CBInstr {
+    RLC(Location),
+    RRC(Location),
+    RL(Location),
+    RR(Location),
+    SLA(Location),
+    SRA(Location),
+    SWAP(Location),
+    SRL(Location),
+    BIT(u8, Location), // (Bit, Target)
+    RES(u8, Location),
+    SET(u8, Location),
+}
+
+impl CBInstr {
+    pub fn location(self) -> Location {
+        match self {
+            RLC(l) | RRC(l) | RL(l) | RR(l) | SLA(l) | SRA(l) | SWAP(l) | SRL(l) => l,
+            BIT(_, l) | RES(_, l) | SET(_, l) => l,
+        }
+    }
+}
+
+// Assembler syntax, e.g. `BIT 7,(HL)`.
+impl Display for CBInstr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let target = match self.location() {
+            Memory(r) => format!("({:?})", r),
+            Register(r) => format!("{:?}", r),
+            l => format!("{:?}", l),
+        };
+        match self {
+            RLC(_) => write!(f, "RLC {}", target),
+            RRC(_) => write!(f, "RRC {}", target),
+            RL(_) => write!(f, "RL {}", target),
+            RR(_) => write!(f, "RR {}", target),
+            SLA(_) => write!(f, "SLA {}", target),
+            SRA(_) => write!(f, "SRA {}", target),
+            SWAP(_) => write!(f, "SWAP {}", target),
+            SRL(_) => write!(f, "SRL {}", target),
+            BIT(bit, _) => write!(f, "BIT {},{}", bit, target),
+            RES(bit, _) => write!(f, "RES {},{}", bit, target),
+            SET(bit, _) => write!(f, "SET {},{}", bit, target),
+        }
+    }
+}
+
+impl From<CBInstr> for u8 {
+    fn from(instr: CBInstr) -> u8 {
+        let target = match instr.location() {
+            Register(B) => 0,
+            Register(C) => 1,
+            Register(D) => 2,
+            Register(E) => 3,
+            Register(H) => 4,
+            Register(L) => 5,
+            Memory(HL) => 6,
+            _ => 7,
+        };
+        let op = match instr {
+            RLC(_) => 0x00,
+            RRC(_) => 0x08,
+            RL(_) => 0x10,
+            RR(_) => 0x18,
+            SLA(_) => 0x20,
+            SRA(_) => 0x28,
+            SWAP(_) => 0x30,
+            SRL(_) => 0x38,
+            BIT(bit, _) => 0x40 | bit << 3,
+            RES(bit, _) => 0x80 | bit << 3,
+            SET(bit, _) => 0xC0 | bit << 3,
+        };
+        op | target
+    }
+}
+
 impl Into<u8> for Instr {
     fn into(self) -> u8 {
         match self {
             NOOP => 0x00,
-            LD(Register(BC), Immediate(2)) => 0x01,
+            LD16(Pair(BC), Immediate16) => 0x01,
             LD(Memory(BC), Register(A)) => 0x02,
-            INC(Register(BC)) => 0x03,
+            INC16(BC) => 0x03,
             INC(Register(B)) => 0x04,
             DEC(Register(B)) => 0x05,
-            LD(Register(B), Immediate(1)) => 0x06,
+            LD(Register(B), Immediate) => 0x06,
             RLCA => 0x07,
-            LD(Immediate(2), Register(SP)) => 0x08,
-            ADDHL(Register(BC)) => 0x09,
+            LD16(MemoryImmediate16, Pair(SP)) => 0x08,
+            ADDHL(BC) => 0x09,
             LD(Register(A), Memory(BC)) => 0x0A,
-            DEC(Register(BC)) => 0x0B,
+            DEC16(BC) => 0x0B,
             INC(Register(C)) => 0x0C,
             DEC(Register(C)) => 0x0D,
-            LD(Register(C), Immediate(1)) => 0x0E,
+            LD(Register(C), Immediate) => 0x0E,
             RRCA => 0x0F,
             STOP => 0x10,
-            LD(Register(DE), Immediate(2)) => 0x11,
+            LD16(Pair(DE), Immediate16) => 0x11,
             LD(Memory(DE), Register(A)) => 0x12,
-            INC(Register(DE)) => 0x13,
+            INC16(DE) => 0x13,
             INC(Register(D)) => 0x14,
             DEC(Register(D)) => 0x15,
-            LD(Register(D), Immediate(1)) => 0x16,
+            LD(Register(D), Immediate) => 0x16,
             RLA => 0x17,
             JR(None) => 0x18,
-            ADDHL(Register(DE)) => 0x19,
+            ADDHL(DE) => 0x19,
             LD(Register(A), Memory(DE)) => 0x1A,
-            DEC(Register(DE)) => 0x1B,
+            DEC16(DE) => 0x1B,
             INC(Register(E)) => 0x1C,
             DEC(Register(E)) => 0x1D,
-            LD(Register(E), Immediate(1)) => 0x1E,
+            LD(Register(E), Immediate) => 0x1E,
             RRA => 0x1F,
             JR(Some(FlagNZ)) => 0x20,
-            LD(Register(HL), Immediate(2)) => 0x21,
+            LD16(Pair(HL), Immediate16) => 0x21,
             LDI(Memory(HL), Register(A)) => 0x22,
-            INC(Register(HL)) => 0x23,
+            INC16(HL) => 0x23,
             INC(Register(H)) => 0x24,
             DEC(Register(H)) => 0x25,
-            LD(Register(H), Immediate(1)) => 0x26,
+            LD(Register(H), Immediate) => 0x26,
             DAA => 0x27,
             JR(Some(FlagZ)) => 0x28,
-            ADDHL(Register(HL)) => 0x29,
+            ADDHL(HL) => 0x29,
             LDI(Register(A), Memory(HL)) => 0x2A,
-            DEC(Register(HL)) => 0x2B,
+            DEC16(HL) => 0x2B,
             INC(Register(L)) => 0x2C,
             DEC(Register(L)) => 0x2D,
-            LD(Register(L), Immediate(1)) => 0x2E,
+            LD(Register(L), Immediate) => 0x2E,
             NOT(Register(A)) => 0x2F,
             JR(Some(FlagNC)) => 0x30,
-            LD(Register(SP), Immediate(2)) => 0x31,
+            LD16(Pair(SP), Immediate16) => 0x31,
             LDD(Memory(HL), Register(A)) => 0x32,
-            INC(Register(SP)) => 0x33,
+            INC16(SP) => 0x33,
             INC(Memory(HL)) => 0x34,
             DEC(Memory(HL)) => 0x35,
-            LD(Memory(HL), Immediate(1)) => 0x36,
+            LD(Memory(HL), Immediate) => 0x36,
             SCF => 0x37,
             JR(Some(FlagC)) => 0x38,
-            ADDHL(Register(SP)) => 0x39,
+            ADDHL(SP) => 0x39,
             LDD(Register(A), Memory(HL)) => 0x3A,
-            DEC(Register(SP)) => 0x3B,
+            DEC16(SP) => 0x3B,
             INC(Register(A)) => 0x3C,
             DEC(Register(A)) => 0x3D,
-            LD(Register(A), Immediate(1)) => 0x3E,
+            LD(Register(A), Immediate) => 0x3E,
             CCF => 0x3F,
             LD(Register(B), Register(B)) => 0x40,
             LD(Register(B), Register(C)) => 0x41,
@@ -597,9 +467,9 @@ impl Into<u8> for Instr {
             SUB(Register(A)) => 0x97,
             SBC(Register(B)) => 0x98,
             SBC(Register(C)) => 0x99,
-            SBC(Register(D)) => 0x92,
-            SBC(Register(E)) => 0x93,
-            SBC(Register(H)) => 0x94,
+            SBC(Register(D)) => 0x9A,
+            SBC(Register(E)) => 0x9B,
+            SBC(Register(H)) => 0x9C,
             SBC(Register(L)) => 0x9D,
             SBC(Memory(HL)) => 0x9E,
             SBC(Register(A)) => 0x9F,
@@ -636,12 +506,12 @@ impl Into<u8> for Instr {
             CP(Memory(HL)) => 0xBE,
             CP(Register(A)) => 0xBF,
             RET(Some(FlagNZ)) => 0xC0,
-            POP(Register(BC)) => 0xC1,
+            POP(BC) => 0xC1,
             JP(Some(FlagNZ)) => 0xC2,
             JP(None) => 0xC3,
             CALL(Some(FlagNZ)) => 0xC4,
-            PUSH(Register(BC)) => 0xC5,
-            ADD(Immediate(1)) => 0xC6,
+            PUSH(BC) => 0xC5,
+            ADD(Immediate) => 0xC6,
             RST(0x0) => 0xC7,
             RET(Some(FlagZ)) => 0xC8,
             RET(None) => 0xC9,
@@ -649,44 +519,44 @@ impl Into<u8> for Instr {
             CB => 0xCB,
             CALL(Some(FlagZ)) => 0xCC,
             CALL(None) => 0xCD,
-            ADC(Immediate(1)) => 0xCE,
+            ADC(Immediate) => 0xCE,
             RST(0x8) => 0xCF,
             RET(Some(FlagNC)) => 0xD0,
-            POP(Register(DE)) => 0xD1,
+            POP(DE) => 0xD1,
             JP(Some(FlagNC)) => 0xD2,
             CALL(Some(FlagNC)) => 0xD4,
-            PUSH(Register(DE)) => 0xD5,
-            SUB(Immediate(1)) => 0xD6,
+            PUSH(DE) => 0xD5,
+            SUB(Immediate) => 0xD6,
             RST(0x10) => 0xD7,
             RET(Some(FlagC)) => 0xD8,
             RETI => 0xD9,
             JP(Some(FlagC)) => 0xDA,
             CALL(Some(FlagC)) => 0xDC,
-            SBC(Immediate(1)) => 0xDE,
+            SBC(Immediate) => 0xDE,
             RST(0x18) => 0xDF,
             LD(MemOffsetImm, Register(A)) => 0xE0,
-            POP(Register(HL)) => 0xE1,
+            POP(HL) => 0xE1,
             LD(MemOffsetC, Register(A)) => 0xE2,
-            PUSH(Register(HL)) => 0xE5,
-            AND(Immediate(1)) => 0xE6,
+            PUSH(HL) => 0xE5,
+            AND(Immediate) => 0xE6,
             RST(0x20) => 0xE7,
             ADDSP => 0xE8,
             JpHl => 0xE9,
             LD(MemoryImmediate, Register(A)) => 0xEA,
-            XOR(Immediate(1)) => 0xEE,
+            XOR(Immediate) => 0xEE,
             RST(0x28) => 0xEF,
             LD(Register(A), MemOffsetImm) => 0xF0,
-            POP(Register(AF)) => 0xF1,
+            POP(AF) => 0xF1,
             LD(Register(A), MemOffsetC) => 0xF2,
             DisableInterrupts => 0xF3,
-            PUSH(Register(AF)) => 0xF5,
-            OR(Immediate(1)) => 0xF6,
+            PUSH(AF) => 0xF5,
+            OR(Immediate) => 0xF6,
             RST(0x30) => 0xF7,
             LDSP => 0xF8,
-            LD(Register(SP), Register(HL)) => 0xF9,
+            LD16(Pair(SP), Pair(HL)) => 0xF9,
             LD(Register(A), MemoryImmediate) => 0xFA,
             EnableInterrupts => 0xFB,
-            CP(Immediate(1)) => 0xFE,
+            CP(Immediate) => 0xFE,
             RST(0x38) => 0xFF,
             _ => 0xDB,
         }
@@ -695,68 +565,68 @@ impl Into<u8> for Instr {
 
 pub const INSTR_TABLE: [Instr; 256] = [
     NOOP,                             //0x00
-    LD(Register(BC), Immediate(2)),   //0x01
+    LD16(Pair(BC), Immediate16),       //0x01
     LD(Memory(BC), Register(A)),      //0x02
-    INC(Register(BC)),                //0x03
+    INC16(BC),                         //0x03
     INC(Register(B)),                 //0x04
     DEC(Register(B)),                 //0x05
-    LD(Register(B), Immediate(1)),    //0x06
+    LD(Register(B), Immediate),        //0x06
     RLCA,                             //0x07
-    LD(Immediate(2), Register(SP)),   //0x08
-    ADDHL(Register(BC)),              //0x09
+    LD16(MemoryImmediate16, Pair(SP)), //0x08
+    ADDHL(BC),                         //0x09
     LD(Register(A), Memory(BC)),      //0x0A
-    DEC(Register(BC)),                //0x0B
+    DEC16(BC),                         //0x0B
     INC(Register(C)),                 //0x0C
     DEC(Register(C)),                 //0x0D
-    LD(Register(C), Immediate(1)),    //0x0E
+    LD(Register(C), Immediate),        //0x0E
     RRCA,                             //0x0F
     STOP,                             //0x10
-    LD(Register(DE), Immediate(2)),   //0x11
+    LD16(Pair(DE), Immediate16),       //0x11
     LD(Memory(DE), Register(A)),      //0x12
-    INC(Register(DE)),                //0x13
+    INC16(DE),                         //0x13
     INC(Register(D)),                 //0x14
     DEC(Register(D)),                 //0x15
-    LD(Register(D), Immediate(1)),    //0x16
+    LD(Register(D), Immediate),        //0x16
     RLA,                              //0x17
     JR(None),                         //0x18
-    ADDHL(Register(DE)),              //0x19
+    ADDHL(DE),                         //0x19
     LD(Register(A), Memory(DE)),      //0x1A
-    DEC(Register(DE)),                //0x1B
+    DEC16(DE),                         //0x1B
     INC(Register(E)),                 //0x1C
     DEC(Register(E)),                 //0x1D
-    LD(Register(E), Immediate(1)),    //0x1E
+    LD(Register(E), Immediate),        //0x1E
     RRA,                              //0x1F
     JR(Some(FlagNZ)),                 //0x20
-    LD(Register(HL), Immediate(2)),   //0x21
+    LD16(Pair(HL), Immediate16),       //0x21
     LDI(Memory(HL), Register(A)),     //0x22
-    INC(Register(HL)),                //0x23
+    INC16(HL),                         //0x23
     INC(Register(H)),                 //0x24
     DEC(Register(H)),                 //0x25
-    LD(Register(H), Immediate(1)),    //0x26
+    LD(Register(H), Immediate),        //0x26
     DAA,                              //0x27
     JR(Some(FlagZ)),                  //0x28
-    ADDHL(Register(HL)),              //0x29
+    ADDHL(HL),                         //0x29
     LDI(Register(A), Memory(HL)),     //0x2A
-    DEC(Register(HL)),                //0x2B
+    DEC16(HL),                         //0x2B
     INC(Register(L)),                 //0x2C
     DEC(Register(L)),                 //0x2D
-    LD(Register(L), Immediate(1)),    //0x2E
+    LD(Register(L), Immediate),        //0x2E
     NOT(Register(A)),                 //0x2F
     JR(Some(FlagNC)),                 //0x30
-    LD(Register(SP), Immediate(2)),   //0x31
+    LD16(Pair(SP), Immediate16),       //0x31
     LDD(Memory(HL), Register(A)),     //0x32
-    INC(Register(SP)),                //0x33
+    INC16(SP),                         //0x33
     INC(Memory(HL)),                  //0x34
     DEC(Memory(HL)),                  //0x35
-    LD(Memory(HL), Immediate(1)),     //0x36
+    LD(Memory(HL), Immediate),         //0x36
     SCF,                              //0x37
     JR(Some(FlagC)),                  //0x38
-    ADDHL(Register(SP)),              //0x39
+    ADDHL(SP),                         //0x39
     LDD(Register(A), Memory(HL)),     //0x3A
-    DEC(Register(SP)),                //0x3B
+    DEC16(SP),                         //0x3B
     INC(Register(A)),                 //0x3C
     DEC(Register(A)),                 //0x3D
-    LD(Register(A), Immediate(1)),    //0x3E
+    LD(Register(A), Immediate),        //0x3E
     CCF,                              //0x3F
     LD(Register(B), Register(B)),     //0x40
     LD(Register(B), Register(C)),     //0x41
@@ -848,9 +718,9 @@ pub const INSTR_TABLE: [Instr; 256] = [
     SUB(Register(A)),                 //0x97
     SBC(Register(B)),                 //0x98
     SBC(Register(C)),                 //0x99
-    SBC(Register(D)),                 //0x92
-    SBC(Register(E)),                 //0x93
-    SBC(Register(H)),                 //0x94
+    SBC(Register(D)),                 //0x9A
+    SBC(Register(E)),                 //0x9B
+    SBC(Register(H)),                 //0x9C
     SBC(Register(L)),                 //0x9D
     SBC(Memory(HL)),                  //0x9E
     SBC(Register(A)),                 //0x9F
@@ -887,12 +757,12 @@ pub const INSTR_TABLE: [Instr; 256] = [
     CP(Memory(HL)),                   //0xBE
     CP(Register(A)),                  //0xBF
     RET(Some(FlagNZ)),                //0xC0
-    POP(Register(BC)),                //0xC1
+    POP(BC),                           //0xC1
     JP(Some(FlagNZ)),                 //0xC2
     JP(None),                         //0xC3
     CALL(Some(FlagNZ)),               //0xC4
-    PUSH(Register(BC)),               //0xC5
-    ADD(Immediate(1)),                //0xC6
+    PUSH(BC),                          //0xC5
+    ADD(Immediate),                    //0xC6
     RST(0x0),                         //0xC7
     RET(Some(FlagZ)),                 //0xC8
     RET(None),                        //0xC9
@@ -900,15 +770,15 @@ pub const INSTR_TABLE: [Instr; 256] = [
     CB,                               //0xCB
     CALL(Some(FlagZ)),                //0xCC
     CALL(None),                       //0xCD
-    ADC(Immediate(1)),                //0xCE
+    ADC(Immediate),                    //0xCE
     RST(0x8),                         //0xCF
     RET(Some(FlagNC)),                //0xD0
-    POP(Register(DE)),                //0xD1
+    POP(DE),                           //0xD1
     JP(Some(FlagNC)),                 //0xD2
     UNIMPLEMENTED,                    //0xD3
     CALL(Some(FlagNC)),               //0xD4
-    PUSH(Register(DE)),               //0xD5
-    SUB(Immediate(1)),                //0xD6
+    PUSH(DE),                          //0xD5
+    SUB(Immediate),                    //0xD6
     RST(0x10),                        //0xD7
     RET(Some(FlagC)),                 //0xD8
     RETI,                             //0xD9
@@ -916,15 +786,15 @@ pub const INSTR_TABLE: [Instr; 256] = [
     UNIMPLEMENTED,                    //0xDB
     CALL(Some(FlagC)),                //0xDC
     UNIMPLEMENTED,                    //0xDD
-    SBC(Immediate(1)),                //0xDE
+    SBC(Immediate),                    //0xDE
     RST(0x18),                        //0xDF
     LD(MemOffsetImm, Register(A)),    //0xE0
-    POP(Register(HL)),                //0xE1
+    POP(HL),                           //0xE1
     LD(MemOffsetC, Register(A)),      //0xE2
     UNIMPLEMENTED,                    //0xE3
     UNIMPLEMENTED,                    //0xE4
-    PUSH(Register(HL)),               //0xE5
-    AND(Immediate(1)),                //0xE6
+    PUSH(HL),                          //0xE5
+    AND(Immediate),                    //0xE6
     RST(0x20),                        //0xE7
     ADDSP,                            //0xE8
     JpHl,                            //0xE9
@@ -932,23 +802,23 @@ pub const INSTR_TABLE: [Instr; 256] = [
     UNIMPLEMENTED,                    //0xEB
     UNIMPLEMENTED,                    //0xEC
     UNIMPLEMENTED,                    //0xED
-    XOR(Immediate(1)),                //0xEE
+    XOR(Immediate),                    //0xEE
     RST(0x28),                        //0xEF
     LD(Register(A), MemOffsetImm),    //0xF0
-    POP(Register(AF)),                //0xF1
+    POP(AF),                           //0xF1
     LD(Register(A), MemOffsetC),      //0xF2
     DisableInterrupts,                //0xF3
     UNIMPLEMENTED,                    //0xF4
-    PUSH(Register(AF)),               //0xF5
-    OR(Immediate(1)),                 //0xF6
+    PUSH(AF),                          //0xF5
+    OR(Immediate),                     //0xF6
     RST(0x30),                        //0xF7
     LDSP,                             //0xF8
-    LD(Register(SP), Register(HL)),   //0xF9
+    LD16(Pair(SP), Pair(HL)),          //0xF9
     LD(Register(A), MemoryImmediate), //0xFA
     EnableInterrupts,                 //0xFB
     UNIMPLEMENTED,                    //0xFC
     UNIMPLEMENTED,                    //0xFD
-    CP(Immediate(1)),                 //0xFE
+    CP(Immediate),                     //0xFE
     RST(0x38),                        //0xFF
 ];
 
@@ -1210,3 +1080,262 @@ pub const INSTR_DATA_LENGTHS: [usize; 256] = [
     1, // 0xfe
     0, // 0xff
 ];
+
+pub const CB_TABLE: [CBInstr; 256] = [
+    RLC(Register(B)),                 //0x00
+    RLC(Register(C)),                 //0x01
+    RLC(Register(D)),                 //0x02
+    RLC(Register(E)),                 //0x03
+    RLC(Register(H)),                 //0x04
+    RLC(Register(L)),                 //0x05
+    RLC(Memory(HL)),                  //0x06
+    RLC(Register(A)),                 //0x07
+    RRC(Register(B)),                 //0x08
+    RRC(Register(C)),                 //0x09
+    RRC(Register(D)),                 //0x0A
+    RRC(Register(E)),                 //0x0B
+    RRC(Register(H)),                 //0x0C
+    RRC(Register(L)),                 //0x0D
+    RRC(Memory(HL)),                  //0x0E
+    RRC(Register(A)),                 //0x0F
+    RL(Register(B)),                  //0x10
+    RL(Register(C)),                  //0x11
+    RL(Register(D)),                  //0x12
+    RL(Register(E)),                  //0x13
+    RL(Register(H)),                  //0x14
+    RL(Register(L)),                  //0x15
+    RL(Memory(HL)),                   //0x16
+    RL(Register(A)),                  //0x17
+    RR(Register(B)),                  //0x18
+    RR(Register(C)),                  //0x19
+    RR(Register(D)),                  //0x1A
+    RR(Register(E)),                  //0x1B
+    RR(Register(H)),                  //0x1C
+    RR(Register(L)),                  //0x1D
+    RR(Memory(HL)),                   //0x1E
+    RR(Register(A)),                  //0x1F
+    SLA(Register(B)),                 //0x20
+    SLA(Register(C)),                 //0x21
+    SLA(Register(D)),                 //0x22
+    SLA(Register(E)),                 //0x23
+    SLA(Register(H)),                 //0x24
+    SLA(Register(L)),                 //0x25
+    SLA(Memory(HL)),                  //0x26
+    SLA(Register(A)),                 //0x27
+    SRA(Register(B)),                 //0x28
+    SRA(Register(C)),                 //0x29
+    SRA(Register(D)),                 //0x2A
+    SRA(Register(E)),                 //0x2B
+    SRA(Register(H)),                 //0x2C
+    SRA(Register(L)),                 //0x2D
+    SRA(Memory(HL)),                  //0x2E
+    SRA(Register(A)),                 //0x2F
+    SWAP(Register(B)),                //0x30
+    SWAP(Register(C)),                //0x31
+    SWAP(Register(D)),                //0x32
+    SWAP(Register(E)),                //0x33
+    SWAP(Register(H)),                //0x34
+    SWAP(Register(L)),                //0x35
+    SWAP(Memory(HL)),                 //0x36
+    SWAP(Register(A)),                //0x37
+    SRL(Register(B)),                 //0x38
+    SRL(Register(C)),                 //0x39
+    SRL(Register(D)),                 //0x3A
+    SRL(Register(E)),                 //0x3B
+    SRL(Register(H)),                 //0x3C
+    SRL(Register(L)),                 //0x3D
+    SRL(Memory(HL)),                  //0x3E
+    SRL(Register(A)),                 //0x3F
+    BIT(0, Register(B)),              //0x40
+    BIT(0, Register(C)),              //0x41
+    BIT(0, Register(D)),              //0x42
+    BIT(0, Register(E)),              //0x43
+    BIT(0, Register(H)),              //0x44
+    BIT(0, Register(L)),              //0x45
+    BIT(0, Memory(HL)),               //0x46
+    BIT(0, Register(A)),              //0x47
+    BIT(1, Register(B)),              //0x48
+    BIT(1, Register(C)),              //0x49
+    BIT(1, Register(D)),              //0x4A
+    BIT(1, Register(E)),              //0x4B
+    BIT(1, Register(H)),              //0x4C
+    BIT(1, Register(L)),              //0x4D
+    BIT(1, Memory(HL)),               //0x4E
+    BIT(1, Register(A)),              //0x4F
+    BIT(2, Register(B)),              //0x50
+    BIT(2, Register(C)),              //0x51
+    BIT(2, Register(D)),              //0x52
+    BIT(2, Register(E)),              //0x53
+    BIT(2, Register(H)),              //0x54
+    BIT(2, Register(L)),              //0x55
+    BIT(2, Memory(HL)),               //0x56
+    BIT(2, Register(A)),              //0x57
+    BIT(3, Register(B)),              //0x58
+    BIT(3, Register(C)),              //0x59
+    BIT(3, Register(D)),              //0x5A
+    BIT(3, Register(E)),              //0x5B
+    BIT(3, Register(H)),              //0x5C
+    BIT(3, Register(L)),              //0x5D
+    BIT(3, Memory(HL)),               //0x5E
+    BIT(3, Register(A)),              //0x5F
+    BIT(4, Register(B)),              //0x60
+    BIT(4, Register(C)),              //0x61
+    BIT(4, Register(D)),              //0x62
+    BIT(4, Register(E)),              //0x63
+    BIT(4, Register(H)),              //0x64
+    BIT(4, Register(L)),              //0x65
+    BIT(4, Memory(HL)),               //0x66
+    BIT(4, Register(A)),              //0x67
+    BIT(5, Register(B)),              //0x68
+    BIT(5, Register(C)),              //0x69
+    BIT(5, Register(D)),              //0x6A
+    BIT(5, Register(E)),              //0x6B
+    BIT(5, Register(H)),              //0x6C
+    BIT(5, Register(L)),              //0x6D
+    BIT(5, Memory(HL)),               //0x6E
+    BIT(5, Register(A)),              //0x6F
+    BIT(6, Register(B)),              //0x70
+    BIT(6, Register(C)),              //0x71
+    BIT(6, Register(D)),              //0x72
+    BIT(6, Register(E)),              //0x73
+    BIT(6, Register(H)),              //0x74
+    BIT(6, Register(L)),              //0x75
+    BIT(6, Memory(HL)),               //0x76
+    BIT(6, Register(A)),              //0x77
+    BIT(7, Register(B)),              //0x78
+    BIT(7, Register(C)),              //0x79
+    BIT(7, Register(D)),              //0x7A
+    BIT(7, Register(E)),              //0x7B
+    BIT(7, Register(H)),              //0x7C
+    BIT(7, Register(L)),              //0x7D
+    BIT(7, Memory(HL)),               //0x7E
+    BIT(7, Register(A)),              //0x7F
+    RES(0, Register(B)),              //0x80
+    RES(0, Register(C)),              //0x81
+    RES(0, Register(D)),              //0x82
+    RES(0, Register(E)),              //0x83
+    RES(0, Register(H)),              //0x84
+    RES(0, Register(L)),              //0x85
+    RES(0, Memory(HL)),               //0x86
+    RES(0, Register(A)),              //0x87
+    RES(1, Register(B)),              //0x88
+    RES(1, Register(C)),              //0x89
+    RES(1, Register(D)),              //0x8A
+    RES(1, Register(E)),              //0x8B
+    RES(1, Register(H)),              //0x8C
+    RES(1, Register(L)),              //0x8D
+    RES(1, Memory(HL)),               //0x8E
+    RES(1, Register(A)),              //0x8F
+    RES(2, Register(B)),              //0x90
+    RES(2, Register(C)),              //0x91
+    RES(2, Register(D)),              //0x92
+    RES(2, Register(E)),              //0x93
+    RES(2, Register(H)),              //0x94
+    RES(2, Register(L)),              //0x95
+    RES(2, Memory(HL)),               //0x96
+    RES(2, Register(A)),              //0x97
+    RES(3, Register(B)),              //0x98
+    RES(3, Register(C)),              //0x99
+    RES(3, Register(D)),              //0x9A
+    RES(3, Register(E)),              //0x9B
+    RES(3, Register(H)),              //0x9C
+    RES(3, Register(L)),              //0x9D
+    RES(3, Memory(HL)),               //0x9E
+    RES(3, Register(A)),              //0x9F
+    RES(4, Register(B)),              //0xA0
+    RES(4, Register(C)),              //0xA1
+    RES(4, Register(D)),              //0xA2
+    RES(4, Register(E)),              //0xA3
+    RES(4, Register(H)),              //0xA4
+    RES(4, Register(L)),              //0xA5
+    RES(4, Memory(HL)),               //0xA6
+    RES(4, Register(A)),              //0xA7
+    RES(5, Register(B)),              //0xA8
+    RES(5, Register(C)),              //0xA9
+    RES(5, Register(D)),              //0xAA
+    RES(5, Register(E)),              //0xAB
+    RES(5, Register(H)),              //0xAC
+    RES(5, Register(L)),              //0xAD
+    RES(5, Memory(HL)),               //0xAE
+    RES(5, Register(A)),              //0xAF
+    RES(6, Register(B)),              //0xB0
+    RES(6, Register(C)),              //0xB1
+    RES(6, Register(D)),              //0xB2
+    RES(6, Register(E)),              //0xB3
+    RES(6, Register(H)),              //0xB4
+    RES(6, Register(L)),              //0xB5
+    RES(6, Memory(HL)),               //0xB6
+    RES(6, Register(A)),              //0xB7
+    RES(7, Register(B)),              //0xB8
+    RES(7, Register(C)),              //0xB9
+    RES(7, Register(D)),              //0xBA
+    RES(7, Register(E)),              //0xBB
+    RES(7, Register(H)),              //0xBC
+    RES(7, Register(L)),              //0xBD
+    RES(7, Memory(HL)),               //0xBE
+    RES(7, Register(A)),              //0xBF
+    SET(0, Register(B)),              //0xC0
+    SET(0, Register(C)),              //0xC1
+    SET(0, Register(D)),              //0xC2
+    SET(0, Register(E)),              //0xC3
+    SET(0, Register(H)),              //0xC4
+    SET(0, Register(L)),              //0xC5
+    SET(0, Memory(HL)),               //0xC6
+    SET(0, Register(A)),              //0xC7
+    SET(1, Register(B)),              //0xC8
+    SET(1, Register(C)),              //0xC9
+    SET(1, Register(D)),              //0xCA
+    SET(1, Register(E)),              //0xCB
+    SET(1, Register(H)),              //0xCC
+    SET(1, Register(L)),              //0xCD
+    SET(1, Memory(HL)),               //0xCE
+    SET(1, Register(A)),              //0xCF
+    SET(2, Register(B)),              //0xD0
+    SET(2, Register(C)),              //0xD1
+    SET(2, Register(D)),              //0xD2
+    SET(2, Register(E)),              //0xD3
+    SET(2, Register(H)),              //0xD4
+    SET(2, Register(L)),              //0xD5
+    SET(2, Memory(HL)),               //0xD6
+    SET(2, Register(A)),              //0xD7
+    SET(3, Register(B)),              //0xD8
+    SET(3, Register(C)),              //0xD9
+    SET(3, Register(D)),              //0xDA
+    SET(3, Register(E)),              //0xDB
+    SET(3, Register(H)),              //0xDC
+    SET(3, Register(L)),              //0xDD
+    SET(3, Memory(HL)),               //0xDE
+    SET(3, Register(A)),              //0xDF
+    SET(4, Register(B)),              //0xE0
+    SET(4, Register(C)),              //0xE1
+    SET(4, Register(D)),              //0xE2
+    SET(4, Register(E)),              //0xE3
+    SET(4, Register(H)),              //0xE4
+    SET(4, Register(L)),              //0xE5
+    SET(4, Memory(HL)),               //0xE6
+    SET(4, Register(A)),              //0xE7
+    SET(5, Register(B)),              //0xE8
+    SET(5, Register(C)),              //0xE9
+    SET(5, Register(D)),              //0xEA
+    SET(5, Register(E)),              //0xEB
+    SET(5, Register(H)),              //0xEC
+    SET(5, Register(L)),              //0xED
+    SET(5, Memory(HL)),               //0xEE
+    SET(5, Register(A)),              //0xEF
+    SET(6, Register(B)),              //0xF0
+    SET(6, Register(C)),              //0xF1
+    SET(6, Register(D)),              //0xF2
+    SET(6, Register(E)),              //0xF3
+    SET(6, Register(H)),              //0xF4
+    SET(6, Register(L)),              //0xF5
+    SET(6, Memory(HL)),               //0xF6
+    SET(6, Register(A)),              //0xF7
+    SET(7, Register(B)),              //0xF8
+    SET(7, Register(C)),              //0xF9
+    SET(7, Register(D)),              //0xFA
+    SET(7, Register(E)),              //0xFB
+    SET(7, Register(H)),              //0xFC
+    SET(7, Register(L)),              //0xFD
+    SET(7, Memory(HL)),               //0xFE
+    SET(7, Register(A)),              //0xFF
+];
@@ -0,0 +1,69 @@
+use crate::model::Model;
+
+// Per register masks for the IO area at 0xFF00-0xFF7F.
+// https://gbdev.io/pandocs/Hardware_Reg_List.html
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IoRegister {
+    pub unused: u8,   // Bits that always read back as 1
+    pub writable: u8, // Bits a write can change
+}
+
+const fn reg(unused: u8, writable: u8) -> IoRegister {
+    IoRegister { unused, writable }
+}
+
+// Registers nothing is connected to read 0xFF and ignore writes.
+pub const UNMAPPED: IoRegister = reg(0xFF, 0x00);
+// Write only registers read back as all ones.
+const WRITE_ONLY: IoRegister = reg(0xFF, 0xFF);
+
+pub fn io_register(address: u16, model: Model) -> IoRegister {
+    match address {
+        0xFF00 => reg(0xC0, 0x30), // P1
+        0xFF01 => reg(0x00, 0xFF), // SB
+        0xFF02 => reg(0x7E, 0x81), // SC
+        0xFF04 => reg(0x00, 0xFF), // DIV, any write resets it
+        0xFF05 => reg(0x00, 0xFF), // TIMA
+        0xFF06 => reg(0x00, 0xFF), // TMA
+        0xFF07 => reg(0xF8, 0x07), // TAC
+        0xFF0F => reg(0xE0, 0x1F), // IF
+        0xFF10 => reg(0x80, 0x7F), // NR10
+        0xFF11 => reg(0x3F, 0xFF), // NR11
+        0xFF12 => reg(0x00, 0xFF), // NR12
+        0xFF13 => WRITE_ONLY,      // NR13
+        0xFF14 => reg(0xBF, 0xC7), // NR14
+        0xFF16 => reg(0x3F, 0xFF), // NR21
+        0xFF17 => reg(0x00, 0xFF), // NR22
+        0xFF18 => WRITE_ONLY,      // NR23
+        0xFF19 => reg(0xBF, 0xC7), // NR24
+        0xFF1A => reg(0x7F, 0x80), // NR30
+        0xFF1B => WRITE_ONLY,      // NR31
+        0xFF1C => reg(0x9F, 0x60), // NR32
+        0xFF1D => WRITE_ONLY,      // NR33
+        0xFF1E => reg(0xBF, 0xC7), // NR34
+        0xFF20 => reg(0xFF, 0x3F), // NR41
+        0xFF21 => reg(0x00, 0xFF), // NR42
+        0xFF22 => reg(0x00, 0xFF), // NR43
+        0xFF23 => reg(0xBF, 0xC0), // NR44
+        0xFF24 => reg(0x00, 0xFF), // NR50
+        0xFF25 => reg(0x00, 0xFF), // NR51
+        0xFF26 => reg(0x70, 0x80), // NR52
+        0xFF30..=0xFF3F => reg(0x00, 0xFF), // Wave RAM
+        0xFF40 => reg(0x00, 0xFF), // LCDC
+        0xFF41 => reg(0x80, 0x78), // STAT, mode and coincidence bits are read only
+        0xFF42 => reg(0x00, 0xFF), // SCY
+        0xFF43 => reg(0x00, 0xFF), // SCX
+        0xFF44 => reg(0x00, 0x00), // LY
+        0xFF45 => reg(0x00, 0xFF), // LYC
+        0xFF46 => reg(0x00, 0xFF), // DMA
+        0xFF47 => reg(0x00, 0xFF), // BGP
+        0xFF48 => reg(0x00, 0xFF), // OBP0
+        0xFF49 => reg(0x00, 0xFF), // OBP1
+        0xFF4A => reg(0x00, 0xFF), // WY
+        0xFF4B => reg(0x00, 0xFF), // WX
+        0xFF4D if model.is_cgb() => reg(0x7E, 0x01), // KEY1
+        0xFF50 => reg(0xFF, 0xFF), // Boot ROM disable
+        // Everything else, including the CGB registers on DMG, is open bus.
+        _ => UNMAPPED,
+    }
+}
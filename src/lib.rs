@@ -1,8 +1,13 @@
 pub mod bus;
+pub mod cartridge;
+pub mod cheats;
 pub mod cpu;
 pub mod emu;
 pub mod gpu;
 pub mod instructions;
+pub mod io;
+pub mod model;
+pub mod patch;
 pub mod registers;
 pub mod texture;
 // pub mod tui;
@@ -0,0 +1,142 @@
+use std::str::FromStr;
+
+use crate::registers::RegisterState;
+
+// Hardware revisions we know about. Software mostly can't tell them apart,
+// but they differ in details like the unusable memory area.
+#[derive(Debug, Default, Clone, Copy, PartialEq)]
+pub enum Model {
+    Dmg0,
+    #[default]
+    Dmg,
+    Mgb,
+    Sgb,
+    Sgb2,
+    Cgb,
+    Agb,
+}
+
+impl Model {
+    pub fn is_cgb(self) -> bool {
+        matches!(self, Model::Cgb | Model::Agb)
+    }
+
+    // Value read from the unusable area at 0xFEA0-0xFEFF.
+    // https://gbdev.io/pandocs/Memory_Map.html#fea0-feff-range
+    pub fn unusable_read(self, address: u16) -> u8 {
+        if self.is_cgb() {
+            // CGB-E and AGB repeat the high nibble of the low address byte.
+            let nibble = address as u8 & 0xF0;
+            nibble | (nibble >> 4)
+        } else {
+            0x00
+        }
+    }
+
+    // CPU registers as the boot ROM leaves them when it jumps to 0x0100.
+    // https://gbdev.io/pandocs/Power_Up_Sequence.html#cpu-registers
+    pub fn boot_registers(self, header_checksum: u8) -> RegisterState {
+        // DMG and MGB leave H and C set, unless the header checksum is 0.
+        let dmg_flags = if header_checksum == 0 { 0x80 } else { 0xB0 };
+        let [a, f, b, c, d, e, h, l] = match self {
+            Model::Dmg0 => [0x01, 0x00, 0xFF, 0x13, 0x00, 0xC1, 0x84, 0x03],
+            Model::Dmg => [0x01, dmg_flags, 0x00, 0x13, 0x00, 0xD8, 0x01, 0x4D],
+            Model::Mgb => [0xFF, dmg_flags, 0x00, 0x13, 0x00, 0xD8, 0x01, 0x4D],
+            Model::Sgb => [0x01, 0x00, 0x00, 0x14, 0x00, 0x00, 0xC0, 0x60],
+            Model::Sgb2 => [0xFF, 0x00, 0x00, 0x14, 0x00, 0x00, 0xC0, 0x60],
+            Model::Cgb => [0x11, 0x80, 0x00, 0x00, 0xFF, 0x56, 0x00, 0x0D],
+            Model::Agb => [0x11, 0x00, 0x01, 0x00, 0xFF, 0x56, 0x00, 0x0D],
+        };
+        RegisterState {
+            a,
+            f,
+            b,
+            c,
+            d,
+            e,
+            h,
+            l,
+            sp: 0xFFFE,
+            pc: 0x0100,
+        }
+    }
+
+    // The timer's internal counter at 0x0100, DIV being its upper byte.
+    // Pan Docs only has DIV for DMG0 and DMG/MGB, the SGBs are assumed to
+    // match the DMG and CGB keeps the value we always started from.
+    pub fn boot_div(self) -> u16 {
+        match self {
+            Model::Dmg0 => 0x1830,
+            Model::Dmg | Model::Mgb | Model::Sgb | Model::Sgb2 => 0xABCC,
+            Model::Cgb | Model::Agb => 0x1EA0,
+        }
+    }
+
+    // IO registers as the boot ROM leaves them, in the order they're written.
+    // https://gbdev.io/pandocs/Power_Up_Sequence.html#hardware-registers
+    pub fn boot_io(self) -> Vec<(u16, u8)> {
+        let nr52 = match self {
+            Model::Sgb | Model::Sgb2 => 0xF0,
+            _ => 0xF1,
+        };
+        let sc = if self.is_cgb() { 0x7F } else { 0x7E };
+        vec![
+            (0xFF02, sc),   // SC
+            (0xFF05, 0x00), // TIMA
+            (0xFF06, 0x00), // TMA
+            (0xFF07, 0xF8), // TAC
+            (0xFF0F, 0xE1), // IF
+            (0xFF10, 0x80), // NR10
+            (0xFF11, 0xBF), // NR11
+            (0xFF12, 0xF3), // NR12
+            (0xFF13, 0xFF), // NR13
+            (0xFF14, 0xBF), // NR14
+            (0xFF16, 0x3F), // NR21
+            (0xFF17, 0x00), // NR22
+            (0xFF18, 0xFF), // NR23
+            (0xFF19, 0xBF), // NR24
+            (0xFF1A, 0x7F), // NR30
+            (0xFF1B, 0xFF), // NR31
+            (0xFF1C, 0x9F), // NR32
+            (0xFF1D, 0xFF), // NR33
+            (0xFF1E, 0xBF), // NR34
+            (0xFF20, 0xFF), // NR41
+            (0xFF21, 0x00), // NR42
+            (0xFF22, 0x00), // NR43
+            (0xFF23, 0xBF), // NR44
+            (0xFF24, 0x77), // NR50
+            (0xFF25, 0xF3), // NR51
+            (0xFF26, nr52), // NR52
+            (0xFF40, 0x91), // LCDC
+            (0xFF42, 0x00), // SCY
+            (0xFF43, 0x00), // SCX
+            (0xFF45, 0x00), // LYC
+            (0xFF47, 0xFC), // BGP
+            (0xFF48, 0xFF), // OBP0
+            (0xFF49, 0xFF), // OBP1
+            (0xFF4A, 0x00), // WY
+            (0xFF4B, 0x00), // WX
+            (0xFFFF, 0x00), // IE
+        ]
+    }
+}
+
+impl FromStr for Model {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "dmg0" => Ok(Model::Dmg0),
+            "dmg" => Ok(Model::Dmg),
+            "mgb" => Ok(Model::Mgb),
+            "sgb" => Ok(Model::Sgb),
+            "sgb2" => Ok(Model::Sgb2),
+            "cgb" => Ok(Model::Cgb),
+            "agb" => Ok(Model::Agb),
+            _ => Err(format!(
+                "Unknown model {}, expected dmg0, dmg, mgb, sgb, sgb2, cgb or agb",
+                s
+            )),
+        }
+    }
+}
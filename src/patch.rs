@@ -0,0 +1,509 @@
+use std::{error::Error, fmt::Display};
+
+// ROM patch formats, detected from the magic at the start of the file.
+// IPS: https://zerosoft.zophar.net/ips.php
+// UPS: https://www.romhacking.net/documents/392/
+// BPS: https://www.romhacking.net/documents/746/
+const IPS_MAGIC: &[u8] = b"PATCH";
+const IPS_EOF: &[u8] = b"EOF";
+const UPS_MAGIC: &[u8] = b"UPS1";
+const BPS_MAGIC: &[u8] = b"BPS1";
+// UPS and BPS end with the source, target and patch CRC32s.
+const FOOTER_SIZE: usize = 12;
+// The biggest cartridge there is (MBC5), patches can't make anything larger.
+const MAX_ROM_SIZE: usize = 8 << 20;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PatchError {
+    UnknownFormat,
+    // The patch ends in the middle of a record.
+    Truncated,
+    // A copy reads outside the source or target.
+    OutOfBounds(usize),
+    // A number in the patch doesn't fit in a usize.
+    Overflow,
+    TooLarge(usize),
+    SourceSize { expected: usize, actual: usize },
+    SourceChecksum { expected: u32, actual: u32 },
+    TargetChecksum { expected: u32, actual: u32 },
+    PatchChecksum { expected: u32, actual: u32 },
+}
+
+impl Display for PatchError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            PatchError::UnknownFormat => write!(f, "Unknown patch format, expected IPS, UPS or BPS"),
+            PatchError::Truncated => write!(f, "Patch is truncated"),
+            PatchError::OutOfBounds(offset) => {
+                write!(f, "Patch reads out of bounds at offset {:x}", offset)
+            }
+            PatchError::Overflow => write!(f, "Patch contains a number too large to use"),
+            PatchError::TooLarge(size) => write!(
+                f,
+                "Patch makes a {} byte ROM, larger than any cartridge",
+                size
+            ),
+            PatchError::SourceSize { expected, actual } => write!(
+                f,
+                "Patch expects a {} byte ROM, found {} bytes",
+                expected, actual
+            ),
+            PatchError::SourceChecksum { expected, actual } => write!(
+                f,
+                "Patch is for a different ROM: expected CRC32 {:08x}, found {:08x}",
+                expected, actual
+            ),
+            PatchError::TargetChecksum { expected, actual } => write!(
+                f,
+                "Patched ROM is corrupt: expected CRC32 {:08x}, computed {:08x}",
+                expected, actual
+            ),
+            PatchError::PatchChecksum { expected, actual } => write!(
+                f,
+                "Patch file is corrupt: expected CRC32 {:08x}, computed {:08x}",
+                expected, actual
+            ),
+        }
+    }
+}
+
+impl Error for PatchError {}
+
+pub fn crc32(data: &[u8]) -> u32 {
+    !data.iter().fold(!0u32, |crc, b| {
+        (0..8).fold(crc ^ *b as u32, |crc, _| {
+            (crc >> 1) ^ (0xEDB8_8320 & (!(crc & 1)).wrapping_add(1))
+        })
+    })
+}
+
+// Applies `patch` to `rom`, returning the patched copy.
+pub fn apply(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    if patch.starts_with(IPS_MAGIC) {
+        apply_ips(rom, patch)
+    } else if patch.starts_with(UPS_MAGIC) {
+        apply_ups(rom, patch)
+    } else if patch.starts_with(BPS_MAGIC) {
+        apply_bps(rom, patch)
+    } else {
+        Err(PatchError::UnknownFormat)
+    }
+}
+
+struct Reader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(data: &'a [u8], pos: usize) -> Self {
+        Self { data, pos }
+    }
+
+    fn bytes(&mut self, n: usize) -> Result<&'a [u8], PatchError> {
+        let end = self.pos.checked_add(n).ok_or(PatchError::Truncated)?;
+        let bytes = self.data.get(self.pos..end).ok_or(PatchError::Truncated)?;
+        self.pos = end;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8, PatchError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    fn be(&mut self, n: usize) -> Result<usize, PatchError> {
+        Ok(self
+            .bytes(n)?
+            .iter()
+            .fold(0, |x, b| (x << 8) | *b as usize))
+    }
+
+    // Variable length integer used by UPS and BPS.
+    fn varint(&mut self) -> Result<usize, PatchError> {
+        let mut value = 0usize;
+        let mut shift = 1usize;
+        loop {
+            let x = self.byte()?;
+            value = ((x & 0x7F) as usize)
+                .checked_mul(shift)
+                .and_then(|x| value.checked_add(x))
+                .ok_or(PatchError::Overflow)?;
+            if x & 0x80 != 0 {
+                return Ok(value);
+            }
+            shift = shift.checked_mul(0x80).ok_or(PatchError::Overflow)?;
+            value = value.checked_add(shift).ok_or(PatchError::Overflow)?;
+        }
+    }
+
+    // A target size, which is allocated up front so has to be sane.
+    fn size(&mut self) -> Result<usize, PatchError> {
+        let size = self.varint()?;
+        if size > MAX_ROM_SIZE {
+            return Err(PatchError::TooLarge(size));
+        }
+        Ok(size)
+    }
+
+    fn u32_le(&mut self) -> Result<u32, PatchError> {
+        let bytes = self.bytes(4)?;
+        Ok(u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+}
+
+fn apply_ips(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let mut out = rom.to_vec();
+    let mut reader = Reader::new(patch, IPS_MAGIC.len());
+    loop {
+        let offset = reader.bytes(3)?;
+        if offset == IPS_EOF {
+            break;
+        }
+        let offset = offset.iter().fold(0, |x, b| (x << 8) | *b as usize);
+        let (size, data) = match reader.be(2)? {
+            // Size 0 is a run of a single byte.
+            0 => (reader.be(2)?, None),
+            size => (size, Some(reader.bytes(size)?)),
+        };
+        if offset + size > MAX_ROM_SIZE {
+            return Err(PatchError::TooLarge(offset + size));
+        }
+        if out.len() < offset + size {
+            out.resize(offset + size, 0);
+        }
+        match data {
+            Some(data) => out[offset..offset + size].copy_from_slice(data),
+            None => {
+                let value = reader.byte()?;
+                out[offset..offset + size].iter_mut().for_each(|b| *b = value);
+            }
+        }
+    }
+    // Some patches append the size to truncate the ROM to.
+    if let Ok(size) = reader.be(3) {
+        out.truncate(size);
+    }
+    Ok(out)
+}
+
+// Checks the trailing CRCs and returns them as (source, target).
+fn check_footer(rom: &[u8], patch: &[u8]) -> Result<(u32, u32), PatchError> {
+    if patch.len() < FOOTER_SIZE + 4 {
+        return Err(PatchError::Truncated);
+    }
+    let mut footer = Reader::new(patch, patch.len() - FOOTER_SIZE);
+    let (source, target, expected) = (footer.u32_le()?, footer.u32_le()?, footer.u32_le()?);
+    let actual = crc32(&patch[..patch.len() - 4]);
+    if actual != expected {
+        return Err(PatchError::PatchChecksum { expected, actual });
+    }
+    let actual = crc32(rom);
+    if actual != source {
+        return Err(PatchError::SourceChecksum {
+            expected: source,
+            actual,
+        });
+    }
+    Ok((source, target))
+}
+
+fn check_target(out: &[u8], expected: u32) -> Result<(), PatchError> {
+    let actual = crc32(out);
+    if actual != expected {
+        return Err(PatchError::TargetChecksum { expected, actual });
+    }
+    Ok(())
+}
+
+fn check_source_size(rom: &[u8], expected: usize) -> Result<(), PatchError> {
+    if rom.len() != expected {
+        return Err(PatchError::SourceSize {
+            expected,
+            actual: rom.len(),
+        });
+    }
+    Ok(())
+}
+
+fn apply_ups(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let (_, target_crc) = check_footer(rom, patch)?;
+    let end = patch.len() - FOOTER_SIZE;
+    let mut reader = Reader::new(&patch[..end], UPS_MAGIC.len());
+    check_source_size(rom, reader.varint()?)?;
+    let mut out = vec![0; reader.size()?];
+    out.iter_mut().zip(rom.iter()).for_each(|(b, s)| *b = *s);
+    let mut pos = 0usize;
+    while reader.pos < end {
+        pos = pos
+            .checked_add(reader.varint()?)
+            .ok_or(PatchError::Overflow)?;
+        // XOR the source with the patch up to and including a 0 byte.
+        loop {
+            let x = reader.byte()?;
+            // Bytes past the end of either file count as 0.
+            if pos < out.len() {
+                out[pos] = rom.get(pos).copied().unwrap_or(0) ^ x;
+            }
+            pos = pos.checked_add(1).ok_or(PatchError::Overflow)?;
+            if x == 0 {
+                break;
+            }
+        }
+    }
+    check_target(&out, target_crc)?;
+    Ok(out)
+}
+
+fn apply_bps(rom: &[u8], patch: &[u8]) -> Result<Vec<u8>, PatchError> {
+    let (_, target_crc) = check_footer(rom, patch)?;
+    let end = patch.len() - FOOTER_SIZE;
+    let mut reader = Reader::new(&patch[..end], BPS_MAGIC.len());
+    check_source_size(rom, reader.varint()?)?;
+    let target_size = reader.size()?;
+    let metadata = reader.varint()?;
+    reader.bytes(metadata)?;
+
+    let mut out = Vec::with_capacity(target_size);
+    let mut source_offset = 0isize;
+    let mut target_offset = 0isize;
+    // Relative offsets are stored as magnitude << 1 | sign.
+    fn relative(reader: &mut Reader, offset: &mut isize) -> Result<(), PatchError> {
+        let data = reader.varint()?;
+        let delta = (data >> 1) as isize;
+        let delta = if data & 1 != 0 { -delta } else { delta };
+        *offset = offset.checked_add(delta).ok_or(PatchError::Overflow)?;
+        Ok(())
+    }
+    while reader.pos < end {
+        let data = reader.varint()?;
+        let length = (data >> 2) + 1;
+        // Nothing may write past the target size, which also keeps every range
+        // below from overflowing.
+        if length > target_size - out.len() {
+            return Err(PatchError::TooLarge(out.len().saturating_add(length)));
+        }
+        match data & 0b11 {
+            // SourceRead
+            0 => {
+                let start = out.len();
+                let bytes = rom
+                    .get(start..start + length)
+                    .ok_or(PatchError::OutOfBounds(start))?;
+                out.extend_from_slice(bytes);
+            }
+            // TargetRead
+            1 => out.extend_from_slice(reader.bytes(length)?),
+            // SourceCopy
+            2 => {
+                relative(&mut reader, &mut source_offset)?;
+                let start = source_offset as usize;
+                let bytes = Some(start)
+                    .filter(|_| source_offset >= 0)
+                    .and_then(|start| rom.get(start..start.checked_add(length)?))
+                    .ok_or(PatchError::OutOfBounds(start))?;
+                out.extend_from_slice(bytes);
+                source_offset += length as isize;
+            }
+            // TargetCopy, may overlap with the bytes it is writing.
+            _ => {
+                relative(&mut reader, &mut target_offset)?;
+                for _ in 0..length {
+                    let start = target_offset as usize;
+                    if target_offset < 0 || start >= out.len() {
+                        return Err(PatchError::OutOfBounds(start));
+                    }
+                    out.push(out[start]);
+                    target_offset += 1;
+                }
+            }
+        }
+    }
+    if out.len() != target_size {
+        return Err(PatchError::Truncated);
+    }
+    check_target(&out, target_crc)?;
+    Ok(out)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn varint(mut value: usize, out: &mut Vec<u8>) {
+        loop {
+            let x = (value & 0x7F) as u8;
+            value >>= 7;
+            if value == 0 {
+                out.push(0x80 | x);
+                return;
+            }
+            out.push(x);
+            value -= 1;
+        }
+    }
+
+    fn footer(mut patch: Vec<u8>, source: &[u8], target: &[u8]) -> Vec<u8> {
+        patch.extend_from_slice(&crc32(source).to_le_bytes());
+        patch.extend_from_slice(&crc32(target).to_le_bytes());
+        let crc = crc32(&patch);
+        patch.extend_from_slice(&crc.to_le_bytes());
+        patch
+    }
+
+    #[test]
+    fn crc() {
+        assert_eq!(crc32(b"123456789"), 0xCBF4_3926);
+        let mut out = vec![];
+        varint(0x1234, &mut out);
+        assert_eq!(Reader::new(&out, 0).varint(), Ok(0x1234));
+        varint(usize::MAX, &mut out);
+        assert_eq!(Reader::new(&out[2..], 0).varint(), Ok(usize::MAX));
+        // A byte more than usize::MAX needs.
+        assert_eq!(
+            Reader::new(&[0x7F; 11], 0).varint(),
+            Err(PatchError::Overflow)
+        );
+        assert_eq!(
+            Reader::new(&out, 1).bytes(usize::MAX),
+            Err(PatchError::Truncated)
+        );
+    }
+
+    #[test]
+    fn huge_sizes() {
+        let rom = vec![0; 8];
+        let mut patch = UPS_MAGIC.to_vec();
+        varint(rom.len(), &mut patch);
+        varint(usize::MAX, &mut patch);
+        let patch = footer(patch, &rom, &rom);
+        assert_eq!(apply(&rom, &patch), Err(PatchError::TooLarge(usize::MAX)));
+
+        // A UPS hunk at the very end of the address space.
+        let mut patch = UPS_MAGIC.to_vec();
+        varint(rom.len(), &mut patch);
+        varint(rom.len(), &mut patch);
+        varint(usize::MAX, &mut patch);
+        patch.push(0);
+        let patch = footer(patch, &rom, &rom);
+        assert_eq!(apply(&rom, &patch), Err(PatchError::Overflow));
+
+        // An IPS record ending past 8 MiB.
+        let mut patch = IPS_MAGIC.to_vec();
+        patch.extend_from_slice(&[0xFF, 0xFF, 0xFF, 0, 1, 0xAA]);
+        patch.extend_from_slice(IPS_EOF);
+        assert_eq!(apply(&rom, &patch), Err(PatchError::TooLarge(0x100_0000)));
+
+        let mut patch = BPS_MAGIC.to_vec();
+        varint(rom.len(), &mut patch);
+        varint(MAX_ROM_SIZE + 1, &mut patch);
+        let bad = footer(patch, &rom, &rom);
+        assert_eq!(
+            apply(&rom, &bad),
+            Err(PatchError::TooLarge(MAX_ROM_SIZE + 1))
+        );
+
+        // A TargetCopy far longer than the target.
+        let mut patch = BPS_MAGIC.to_vec();
+        varint(rom.len(), &mut patch);
+        varint(4, &mut patch);
+        varint(0, &mut patch);
+        varint((1 << 2) | 1, &mut patch);
+        patch.extend_from_slice(b"ab");
+        varint(((usize::MAX >> 2) << 2) | 3, &mut patch);
+        varint(0, &mut patch);
+        let bad = footer(patch, &rom, &rom);
+        assert!(matches!(apply(&rom, &bad), Err(PatchError::TooLarge(_))));
+
+        // A SourceCopy from a huge negative offset.
+        let mut patch = BPS_MAGIC.to_vec();
+        varint(rom.len(), &mut patch);
+        varint(4, &mut patch);
+        varint(0, &mut patch);
+        varint(2, &mut patch);
+        varint(usize::MAX, &mut patch);
+        let bad = footer(patch, &rom, &rom);
+        assert!(matches!(
+            apply(&rom, &bad),
+            Err(PatchError::OutOfBounds(_))
+        ));
+    }
+
+    #[test]
+    fn ips() {
+        let rom = vec![0; 8];
+        let mut patch = IPS_MAGIC.to_vec();
+        patch.extend_from_slice(&[0, 0, 1, 0, 2, 0xAA, 0xBB]);
+        // RLE record that grows the ROM.
+        patch.extend_from_slice(&[0, 0, 7, 0, 0, 0, 3, 0xCC]);
+        patch.extend_from_slice(IPS_EOF);
+        assert_eq!(
+            apply(&rom, &patch),
+            Ok(vec![0, 0xAA, 0xBB, 0, 0, 0, 0, 0xCC, 0xCC, 0xCC])
+        );
+        patch.extend_from_slice(&[0, 0, 4]);
+        assert_eq!(apply(&rom, &patch), Ok(vec![0, 0xAA, 0xBB, 0]));
+        assert_eq!(
+            apply(&rom, &patch[..patch.len() - 6]),
+            Err(PatchError::Truncated)
+        );
+    }
+
+    #[test]
+    fn ups() {
+        let rom = b"Hello world".to_vec();
+        let target = b"Jello World!".to_vec();
+        let mut patch = UPS_MAGIC.to_vec();
+        varint(rom.len(), &mut patch);
+        varint(target.len(), &mut patch);
+        patch.extend_from_slice(&[0x80, b'H' ^ b'J', 0]);
+        varint(4, &mut patch);
+        patch.extend_from_slice(&[b'w' ^ b'W', 0]);
+        // Past the end of the source, so this XORs with 0.
+        varint(3, &mut patch);
+        patch.extend_from_slice(&[b'!', 0]);
+        let patch = footer(patch, &rom, &target);
+        assert_eq!(apply(&rom, &patch), Ok(target));
+
+        let other = b"Hello wurld".to_vec();
+        assert!(matches!(
+            apply(&other, &patch),
+            Err(PatchError::SourceChecksum { .. })
+        ));
+    }
+
+    #[test]
+    fn bps() {
+        let rom = b"abcdef".to_vec();
+        let target = b"abcXYXYXdef".to_vec();
+        let mut patch = BPS_MAGIC.to_vec();
+        varint(rom.len(), &mut patch);
+        varint(target.len(), &mut patch);
+        varint(0, &mut patch);
+        // SourceRead "abc"
+        varint(2 << 2, &mut patch);
+        // TargetRead "XY"
+        varint((1 << 2) | 1, &mut patch);
+        patch.extend_from_slice(b"XY");
+        // TargetCopy "XYX" from offset 3, overlapping what it writes.
+        varint((2 << 2) | 3, &mut patch);
+        varint(3 << 1, &mut patch);
+        // SourceCopy "def" from offset 3.
+        varint((2 << 2) | 2, &mut patch);
+        varint(3 << 1, &mut patch);
+        let good = footer(patch.clone(), &rom, &target);
+        assert_eq!(apply(&rom, &good), Ok(target.clone()));
+
+        let bad = footer(patch, &rom, b"something else");
+        assert!(matches!(
+            apply(&rom, &bad),
+            Err(PatchError::TargetChecksum { .. })
+        ));
+        let mut corrupt = good.clone();
+        corrupt[10] ^= 1;
+        assert!(matches!(
+            apply(&rom, &corrupt),
+            Err(PatchError::PatchChecksum { .. })
+        ));
+        assert_eq!(apply(&rom, b"nope"), Err(PatchError::UnknownFormat));
+    }
+}
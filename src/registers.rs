@@ -1,7 +1,5 @@
-use crate::cpu::value::Value;
-
-use crate::instructions::Register;
-use crate::instructions::Register::*;
+use crate::instructions::Register16::*;
+use crate::instructions::{Register, Register16};
 use std::fmt;
 
 // Global emu struct.
@@ -43,24 +41,6 @@ macro_rules! u8_reg {
         }
     };
 }
-macro_rules! INC {
-    ($self: ident, $r1: ident) => {{
-        let prev = $self.$r1;
-        let result = prev.wrapping_add(1);
-        $self.f = flags(result == 0, false, (prev & 0x0f) == 0x0f, $self.flg_c());
-        $self.$r1 = result;
-    }};
-}
-
-macro_rules! DEC {
-    ($self: ident, $r1: ident) => {{
-        let prev = $self.$r1;
-        let result = prev.wrapping_sub(1);
-        $self.f = flags(result == 0, true, result & 0x0f == 0x0f, $self.flg_c());
-        $self.$r1 = result;
-    }};
-}
-
 impl RegisterState {
     pub fn new() -> Self {
         Default::default()
@@ -86,128 +66,72 @@ impl RegisterState {
         }
     }
 
-    pub fn inc(&mut self, reg: Register) {
-        match reg {
-            HL => {
-                let n = self.hl().wrapping_add(1);
-                let [h, l] = n.to_be_bytes();
-                self.h = h;
-                self.l = l;
-            }
-            BC => {
-                let n = self.bc().wrapping_add(1);
-                let [b, c] = n.to_be_bytes();
-                self.b = b;
-                self.c = c;
-            }
-            DE => {
-                let n = self.de().wrapping_add(1);
-                let [d, e] = n.to_be_bytes();
-                self.d = d;
-                self.e = e;
-            }
-            SP => {
-                self.sp = self.sp().wrapping_add(1);
-            }
-            A => INC!(self, a),
-            B => INC!(self, b),
-            C => INC!(self, c),
-            D => INC!(self, d),
-            E => INC!(self, e),
-            H => INC!(self, h),
-            L => INC!(self, l),
-            _ => panic!("inc not impl for {:?}", reg),
-        }
+    pub fn inc(&mut self, reg: Register16) {
+        self.set_u16(reg, self.fetch_u16(reg).wrapping_add(1));
     }
 
-    pub fn dec(&mut self, reg: Register) {
+    pub fn dec(&mut self, reg: Register16) {
+        self.set_u16(reg, self.fetch_u16(reg).wrapping_sub(1));
+    }
+
+    pub fn fetch_u8(&self, reg: Register) -> u8 {
         match reg {
-            HL => {
-                let n = self.hl().wrapping_sub(1);
-                let [h, l] = n.to_be_bytes();
-                self.h = h;
-                self.l = l;
-            }
-            BC => {
-                let n = self.bc().wrapping_sub(1);
-                let [b, c] = n.to_be_bytes();
-                self.b = b;
-                self.c = c;
-            }
-            DE => {
-                let n = self.de().wrapping_sub(1);
-                let [d, e] = n.to_be_bytes();
-                self.d = d;
-                self.e = e;
-            }
-            SP => {
-                self.sp = self.sp().wrapping_sub(1);
-            }
-            A => DEC!(self, a),
-            B => DEC!(self, b),
-            C => DEC!(self, c),
-            D => DEC!(self, d),
-            E => DEC!(self, e),
-            H => DEC!(self, h),
-            L => DEC!(self, l),
-            _ => panic!("dec not impl for {:?}", reg),
+            Register::A => self.a,
+            Register::B => self.b,
+            Register::C => self.c,
+            Register::D => self.d,
+            Register::E => self.e,
+            Register::H => self.h,
+            Register::L => self.l,
         }
     }
 
-    pub fn fetch_u8(&self, reg: Register) -> u8 {
+    pub fn set_u8(&mut self, reg: Register, value: u8) {
         match reg {
-            A => self.a,
-            B => self.b,
-            C => self.c,
-            D => self.d,
-            E => self.e,
-            F => self.f,
-            _ => unreachable!(),
+            Register::A => self.a = value,
+            Register::B => self.b = value,
+            Register::C => self.c = value,
+            Register::D => self.d = value,
+            Register::E => self.e = value,
+            Register::H => self.h = value,
+            Register::L => self.l = value,
         }
     }
 
-    pub fn fetch_u16(&self, reg: Register) -> u16 {
+    pub fn fetch_u16(&self, reg: Register16) -> u16 {
         match reg {
             SP => self.sp(),
-            PC => self.pc(),
             BC => self.bc(),
             DE => self.de(),
             HL => self.hl(),
             AF => self.af(),
-            _ => panic!(),
         }
     }
 
-    pub fn get_dual_reg(&self, reg: Register) -> Option<u16> {
+    pub fn set_u16(&mut self, reg: Register16, value: u16) {
+        let [hi, lo] = value.to_be_bytes();
         match reg {
-            SP => Some(self.sp()),
-            PC => Some(self.pc()),
-            BC => Some(self.bc()),
-            DE => Some(self.de()),
-            HL => Some(self.hl()),
-            AF => Some(self.af()),
-            _ => None,
+            SP => self.sp = value,
+            BC => {
+                self.b = hi;
+                self.c = lo;
+            }
+            DE => {
+                self.d = hi;
+                self.e = lo;
+            }
+            HL => {
+                self.h = hi;
+                self.l = lo;
+            }
+            // The low nibble of F always reads back as 0.
+            AF => {
+                self.a = hi;
+                self.f = lo & 0xF0;
+            }
         }
     }
 
-    pub fn fetch(&self, reg: Register) -> Value {
-        match reg {
-            A => Value::from(self.a),
-            B => Value::from(self.b),
-            C => Value::from(self.c),
-            D => Value::from(self.d),
-            E => Value::from(self.e),
-            F => Value::from(self.f),
-            H => Value::from(self.h),
-            L => Value::from(self.l),
-            BC => Value::from(self.bc()),
-            DE => Value::from(self.de()),
-            HL => Value::from(self.hl()),
-            AF => Value::from(self.af()),
-            SP => Value::from(self.sp),
-            PC => Value::from(self.pc),
-        }
-    }
     // TODO See if swapping these makes a difference..
     // Probably not
     pub fn flg_z(&self) -> bool {
@@ -301,7 +225,7 @@ mod tests {
             l: 0xFF,
             ..Default::default()
         };
-        reg.inc(Register::HL);
+        reg.inc(HL);
         assert_eq!(reg.hl(), 0xF100);
     }
     #[test]
@@ -311,7 +235,7 @@ mod tests {
             l: 0x00,
             ..Default::default()
         };
-        reg.dec(Register::HL);
+        reg.dec(HL);
         assert_eq!(reg.hl(), 0xFEFF);
     }
 }
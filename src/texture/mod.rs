@@ -1,7 +1,7 @@
 use crate::gpu::PixelData;
 use std::ops::Range;
 
-fn pixel(value: u8) -> u32 {
+pub fn pixel(value: u8) -> u32 {
     match value {
         0b00 => 0xE0F8D0FF, // White
         0b01 => 0x88C070FF, // Light Gray
@@ -11,6 +11,11 @@ fn pixel(value: u8) -> u32 {
     }
 }
 
+// Shade a palette gives to a colour index.
+pub fn shade(palette: u8, index: u8) -> u8 {
+    (palette >> (index << 1)) & 0b11
+}
+
 pub struct Tile {
     pub texture: [[u32; 8]; 8],
 }
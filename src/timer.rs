@@ -1,5 +1,6 @@
 use std::fmt::Display;
 
+use crate::bus::Device;
 use crate::cpu;
 
 pub const DIV: usize = 0xFF04;
@@ -14,6 +15,10 @@ pub struct Timer {
     pub tac: u8,
     pub clock: usize,
     pub internal: u16,
+    // TIMA overflowed this M-cycle and reads 0, TMA gets loaded on the next.
+    overflow: bool,
+    // TIMA was loaded from TMA this M-cycle, so writes to it don't stick.
+    reloading: bool,
 }
 
 impl Timer {
@@ -24,6 +29,8 @@ impl Timer {
             tac: 0,
             clock: 0,
             internal: 0,
+            overflow: false,
+            reloading: false,
         }
     }
 
@@ -31,37 +38,89 @@ impl Timer {
         (self.internal >> 8) as u8
     }
 
-    pub fn update_internal(&mut self, flags: &mut u8, new: u16) {
-        //Falling edge detector
-        let control = self.tac;
-        let clock_select = control & 0b11;
-
-        let mask = match clock_select {
-            0b00 => (1 << 9),
-            0b01 => (1 << 3),
-            0b10 => (1 << 5),
-            0b11 => (1 << 7),
-            _ => unreachable!(),
+    // Input of the falling edge detector: the DIV bit picked by TAC, ANDed
+    // with the enable bit. Anything making it fall bumps TIMA, which includes
+    // writes to DIV and TAC.
+    // https://gbdev.io/pandocs/Timer_Obscure_Behaviour.html
+    fn signal(&self) -> bool {
+        let mask = match self.tac & 0b11 {
+            0b00 => 1 << 9,
+            0b01 => 1 << 3,
+            0b10 => 1 << 5,
+            _ => 1 << 7,
         };
+        self.tac & 0b100 != 0 && self.internal & mask != 0
+    }
+
+    fn increment(&mut self) {
+        let (value, overflow) = self.tima.overflowing_add(1);
+        self.tima = value;
+        self.overflow = overflow;
+    }
 
-        let was_one = self.internal & mask != 0;
+    pub fn update_internal(&mut self, new: u16) {
+        let was_one = self.signal();
         self.internal = new;
-        let now_zero = self.internal & mask == 0;
-        let enable = (control & 0b100) != 0;
-        if enable && was_one && now_zero {
-            let (value, overflow) = self.tima.overflowing_add(1);
-            if overflow {
-                *flags |= cpu::TIMER;
-                self.tima = self.tma;
-            } else {
-                self.tima = value;
-            }
+        if was_one && !self.signal() {
+            self.increment();
+        }
+    }
+
+    fn set_tac(&mut self, value: u8) {
+        let was_one = self.signal();
+        self.tac = 0b1111_1000 | value;
+        if was_one && !self.signal() {
+            self.increment();
         }
     }
 
     pub fn tick_timer_counter(&mut self, flags: &mut u8) {
         self.clock += 1;
-        self.update_internal(flags, self.internal.wrapping_add(1));
+        self.reloading = false;
+        if self.overflow {
+            self.overflow = false;
+            self.reloading = true;
+            self.tima = self.tma;
+            *flags |= cpu::TIMER;
+        }
+        // `internal` counts T-cycles, four per M-cycle.
+        self.update_internal(self.internal.wrapping_add(4));
+    }
+}
+
+impl Device for Timer {
+    fn read(&self, address: u16) -> u8 {
+        match address as usize {
+            DIV => self.div(),
+            TIMA => self.tima,
+            TMA => self.tma,
+            TAC => self.tac,
+            _ => 0xFF,
+        }
+    }
+
+    fn write(&mut self, address: u16, value: u8, _int_flags: &mut u8) {
+        match address as usize {
+            DIV => self.update_internal(0),
+            // Writing during the overflow cycle cancels the reload.
+            TIMA if !self.reloading => {
+                self.tima = value;
+                self.overflow = false;
+            }
+            TIMA => {}
+            TMA => {
+                self.tma = value;
+                if self.reloading {
+                    self.tima = value;
+                }
+            }
+            TAC => self.set_tac(value),
+            _ => {}
+        }
+    }
+
+    fn tick(&mut self, int_flags: &mut u8) {
+        self.tick_timer_counter(int_flags);
     }
 }
 
@@ -73,3 +132,113 @@ impl Display for Timer {
         ))
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Every tick is an M-cycle, four of the T-cycles DIV counts.
+    #[test]
+    fn tick_rates() {
+        let mut timer = Timer::new();
+        let mut flags = 0;
+        for _ in 0..64 {
+            timer.tick(&mut flags);
+        }
+        assert_eq!(timer.div(), 1);
+        for &(tac, period) in &[(0b100, 256), (0b101, 4), (0b110, 16), (0b111, 64)] {
+            timer.write(TAC as u16, tac, &mut flags);
+            timer.write(DIV as u16, 0, &mut flags);
+            timer.tima = 0;
+            for _ in 0..period - 1 {
+                timer.tick(&mut flags);
+            }
+            assert_eq!(timer.tima, 0, "TAC {:03b}", tac);
+            timer.tick(&mut flags);
+            assert_eq!(timer.tima, 1, "TAC {:03b}", tac);
+        }
+    }
+
+    // TAC 0b101 bumps TIMA every 4 M-cycles.
+    fn fast_timer(tima: u8) -> Timer {
+        let mut timer = Timer::new();
+        timer.write(TAC as u16, 0b101, &mut 0);
+        timer.tima = tima;
+        timer
+    }
+
+    fn tick_until_overflow(timer: &mut Timer, flags: &mut u8) {
+        while !timer.overflow {
+            timer.tick(flags);
+        }
+    }
+
+    #[test]
+    fn reload_is_delayed() {
+        let mut timer = fast_timer(0xFF);
+        let mut flags = 0;
+        timer.write(TMA as u16, 0x42, &mut flags);
+        tick_until_overflow(&mut timer, &mut flags);
+        assert_eq!(timer.read(TIMA as u16), 0x00);
+        assert_eq!(flags, 0);
+        timer.tick(&mut flags);
+        assert_eq!(timer.read(TIMA as u16), 0x42);
+        assert_eq!(flags, cpu::TIMER);
+    }
+
+    #[test]
+    fn tima_write_cancels_reload() {
+        let mut timer = fast_timer(0xFF);
+        let mut flags = 0;
+        timer.write(TMA as u16, 0x42, &mut flags);
+        tick_until_overflow(&mut timer, &mut flags);
+        timer.write(TIMA as u16, 0x10, &mut flags);
+        timer.tick(&mut flags);
+        assert_eq!(timer.read(TIMA as u16), 0x10);
+        assert_eq!(flags, 0);
+    }
+
+    #[test]
+    fn writes_while_reloading() {
+        let mut timer = fast_timer(0xFF);
+        let mut flags = 0;
+        tick_until_overflow(&mut timer, &mut flags);
+        // TMA written in the overflow cycle is what gets loaded.
+        timer.write(TMA as u16, 0x42, &mut flags);
+        timer.tick(&mut flags);
+        assert_eq!(timer.read(TIMA as u16), 0x42);
+        // In the reload cycle TIMA writes are lost, TMA writes go through.
+        timer.write(TIMA as u16, 0x10, &mut flags);
+        assert_eq!(timer.read(TIMA as u16), 0x42);
+        timer.write(TMA as u16, 0x24, &mut flags);
+        assert_eq!(timer.read(TIMA as u16), 0x24);
+        timer.tick(&mut flags);
+        timer.write(TIMA as u16, 0x10, &mut flags);
+        assert_eq!(timer.read(TIMA as u16), 0x10);
+    }
+
+    #[test]
+    fn tac_glitch() {
+        let mut timer = fast_timer(0x00);
+        timer.internal = 1 << 3;
+        // Selected bit goes from 1 to 0 by switching to a slower clock.
+        timer.write(TAC as u16, 0b100, &mut 0);
+        assert_eq!(timer.tima, 0x01);
+        timer.internal = 1 << 9;
+        // Disabling the timer with the bit set also counts as a falling edge.
+        timer.write(TAC as u16, 0b000, &mut 0);
+        assert_eq!(timer.tima, 0x02);
+        // As long as it was running in the first place.
+        timer.write(TAC as u16, 0b000, &mut 0);
+        assert_eq!(timer.tima, 0x02);
+    }
+
+    #[test]
+    fn div_write_glitch() {
+        let mut timer = fast_timer(0x00);
+        timer.internal = 1 << 3;
+        timer.write(DIV as u16, 0, &mut 0);
+        assert_eq!(timer.tima, 0x01);
+        assert_eq!(timer.div(), 0);
+    }
+}
@@ -62,12 +62,7 @@ impl Tui {
                             } else {
                                 std.execute(crossterm::style::SetBackgroundColor(Black))?;
                             }
-                            std.execute(Print(format!(
-                                "{:04x}: {:?} {:04x}                     ",
-                                il.addr,
-                                il.instr,
-                                il.data.unwrap_or(0),
-                            )))
+                            std.execute(Print(format!("{}                     ", il)))
                         })?
                         .execute(RestorePosition)?
                         .execute(MoveDown(1))?;